@@ -0,0 +1,27 @@
+-- Market reference data and end-of-day prices.
+
+CREATE TABLE exchange (
+    mic  TEXT PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE company (
+    ticker   TEXT PRIMARY KEY,
+    name     TEXT NOT NULL,
+    sector   TEXT NOT NULL,
+    industry TEXT NOT NULL,
+    mic      TEXT NOT NULL REFERENCES exchange (mic)
+);
+
+CREATE INDEX company_mic_idx ON company (mic);
+
+CREATE TABLE ledger (
+    ticker TEXT NOT NULL REFERENCES company (ticker) ON DELETE CASCADE,
+    date   DATE NOT NULL,
+    open   REAL NOT NULL,
+    close  REAL NOT NULL,
+    volume DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (ticker, date)
+);
+
+CREATE INDEX ledger_date_idx ON ledger (date);
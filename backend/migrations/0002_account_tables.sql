@@ -0,0 +1,98 @@
+-- Accounts, their holdings and everything used to authenticate them.
+
+CREATE TABLE account (
+    id                  SERIAL PRIMARY KEY,
+    login               TEXT NOT NULL UNIQUE,
+    hashed_password     TEXT NOT NULL,
+    salt                TEXT NOT NULL,
+    -- 0 admin, 1 moderator, 2 user
+    security_lvl        INTEGER NOT NULL DEFAULT 2 CHECK (security_lvl BETWEEN 0 AND 2),
+    disabled            BOOLEAN NOT NULL DEFAULT FALSE,
+    sessions_revoked_at TIMESTAMPTZ,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Column order matters: handlers insert into portfolio and watch_list positionally.
+CREATE TABLE portfolio (
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    ticker     TEXT NOT NULL REFERENCES company (ticker),
+    amount     REAL NOT NULL,
+    buy_price  REAL NOT NULL,
+    PRIMARY KEY (account_id, ticker)
+);
+
+CREATE TABLE watch_list (
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    ticker     TEXT NOT NULL REFERENCES company (ticker),
+    PRIMARY KEY (account_id, ticker)
+);
+
+CREATE TABLE refresh_token (
+    id         SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked    BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX refresh_token_account_idx ON refresh_token (account_id);
+
+CREATE TABLE revoked_token (
+    jti        TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX revoked_token_expires_idx ON revoked_token (expires_at);
+
+CREATE TABLE password_reset (
+    id         SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at    TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX password_reset_account_idx ON password_reset (account_id);
+
+CREATE TABLE api_key (
+    id           SERIAL PRIMARY KEY,
+    account_id   INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    name         TEXT NOT NULL,
+    prefix       TEXT NOT NULL,
+    key_hash     TEXT NOT NULL UNIQUE,
+    scopes       TEXT[] NOT NULL,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_used_at TIMESTAMPTZ,
+    expires_at   TIMESTAMPTZ,
+    revoked_at   TIMESTAMPTZ
+);
+
+CREATE INDEX api_key_account_idx ON api_key (account_id);
+
+-- No foreign keys: entries must outlive the accounts they mention.
+CREATE TABLE audit_log (
+    id          BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    action      TEXT NOT NULL,
+    actor_id    INTEGER,
+    subject_id  INTEGER,
+    details     JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+CREATE INDEX audit_log_actor_idx ON audit_log (actor_id, occurred_at);
+CREATE INDEX audit_log_subject_idx ON audit_log (subject_id, occurred_at);
+CREATE INDEX audit_log_action_idx ON audit_log (action, occurred_at);
+
+CREATE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
@@ -0,0 +1,45 @@
+-- Group roles behind the four connection pools in AppState. They cannot log in
+-- themselves; create one login role per pool and make it a member, e.g.
+--   CREATE ROLE marketpower_user LOGIN PASSWORD '...' IN ROLE marketpower_user_group;
+-- DATABASE_URL (admin) is the role that owns the schema and runs these migrations.
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = 'marketpower_auth_group') THEN
+        CREATE ROLE marketpower_auth_group NOLOGIN;
+    END IF;
+    IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = 'marketpower_user_group') THEN
+        CREATE ROLE marketpower_user_group NOLOGIN;
+    END IF;
+    IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = 'marketpower_moderator_group') THEN
+        CREATE ROLE marketpower_moderator_group NOLOGIN;
+    END IF;
+    IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = 'marketpower_admin_group') THEN
+        CREATE ROLE marketpower_admin_group NOLOGIN;
+    END IF;
+END
+$$;
+
+-- DATABASE_URL_AUTH: logins, token bookkeeping, API keys and audit writes.
+GRANT SELECT ON account TO marketpower_auth_group;
+GRANT SELECT, INSERT, UPDATE ON refresh_token, password_reset, api_key TO marketpower_auth_group;
+GRANT SELECT, INSERT ON revoked_token TO marketpower_auth_group;
+GRANT INSERT ON audit_log TO marketpower_auth_group;
+GRANT USAGE ON SEQUENCE refresh_token_id_seq, password_reset_id_seq, api_key_id_seq, audit_log_id_seq
+    TO marketpower_auth_group;
+
+-- DATABASE_URL_USER: market data and the caller's own holdings.
+GRANT SELECT ON exchange, company, ledger TO marketpower_user_group;
+GRANT SELECT, INSERT, UPDATE, DELETE ON portfolio, watch_list TO marketpower_user_group;
+
+-- DATABASE_URL_MOD: everything a user can do plus maintaining market data.
+GRANT marketpower_user_group TO marketpower_moderator_group;
+GRANT INSERT, UPDATE, DELETE ON exchange, company, ledger TO marketpower_moderator_group;
+
+-- DATABASE_URL: full access, including tables added by later migrations.
+GRANT ALL ON ALL TABLES IN SCHEMA public TO marketpower_admin_group;
+GRANT ALL ON ALL SEQUENCES IN SCHEMA public TO marketpower_admin_group;
+ALTER DEFAULT PRIVILEGES IN SCHEMA public GRANT ALL ON TABLES TO marketpower_admin_group;
+ALTER DEFAULT PRIVILEGES IN SCHEMA public GRANT ALL ON SEQUENCES TO marketpower_admin_group;
+-- The audit log stays append-only even for admins.
+REVOKE UPDATE, DELETE, TRUNCATE ON audit_log FROM marketpower_admin_group;
@@ -0,0 +1,30 @@
+-- Full OHLCV bars. Prices become exact decimals so large quotes keep their
+-- cents, volume becomes a share count, and every bar gains high, low and a
+-- close adjusted for splits and dividends. Existing rows only know open and
+-- close, so high and low start out as the larger and smaller of the two and
+-- the adjusted close as the raw close.
+
+ALTER TABLE ledger
+    ALTER COLUMN open TYPE NUMERIC(20, 6) USING open::NUMERIC(20, 6),
+    ALTER COLUMN close TYPE NUMERIC(20, 6) USING close::NUMERIC(20, 6),
+    ALTER COLUMN volume TYPE BIGINT USING round(volume)::BIGINT,
+    ADD COLUMN high NUMERIC(20, 6),
+    ADD COLUMN low NUMERIC(20, 6),
+    ADD COLUMN adj_close NUMERIC(20, 6);
+
+UPDATE ledger SET
+    high = GREATEST(open, close),
+    low = LEAST(open, close),
+    adj_close = close;
+
+ALTER TABLE ledger
+    ALTER COLUMN high SET NOT NULL,
+    ALTER COLUMN low SET NOT NULL,
+    ALTER COLUMN adj_close SET NOT NULL,
+    ADD CONSTRAINT ledger_bar_check CHECK (
+        low > 0
+        AND low <= LEAST(open, close)
+        AND high >= GREATEST(open, close)
+        AND adj_close > 0
+        AND volume >= 0
+    );
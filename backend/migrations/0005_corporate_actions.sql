@@ -0,0 +1,18 @@
+-- Splits and cash dividends, used to back-adjust ledger prices. `value` is the
+-- number of new shares per old share for a split (4 for a 4:1 split, 0.5 for a
+-- 1:2 reverse split) and the cash paid per share for a dividend.
+
+CREATE TYPE corporate_action_kind AS ENUM ('split', 'dividend');
+
+CREATE TABLE corporate_action (
+    id      SERIAL PRIMARY KEY,
+    ticker  TEXT NOT NULL REFERENCES company (ticker) ON DELETE CASCADE,
+    ex_date DATE NOT NULL,
+    kind    corporate_action_kind NOT NULL,
+    value   NUMERIC(20, 6) NOT NULL CHECK (value > 0),
+    UNIQUE (ticker, ex_date, kind)
+);
+
+GRANT SELECT ON corporate_action TO marketpower_user_group;
+GRANT INSERT, UPDATE, DELETE ON corporate_action TO marketpower_moderator_group;
+GRANT USAGE ON SEQUENCE corporate_action_id_seq TO marketpower_moderator_group;
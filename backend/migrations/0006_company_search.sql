@@ -0,0 +1,8 @@
+-- Trigram indexes behind `GET /companies/search`. pg_trgm is a trusted
+-- extension, so the schema owner can install it without superuser rights
+-- as long as it may create objects in the database.
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX company_ticker_trgm_idx ON company USING GIN (ticker gin_trgm_ops);
+CREATE INDEX company_name_trgm_idx ON company USING GIN (name gin_trgm_ops);
@@ -0,0 +1,2 @@
+-- Expired entries of the revocation list are deleted when tokens are revoked.
+GRANT DELETE ON revoked_token TO marketpower_auth_group;
@@ -0,0 +1,7 @@
+-- Holdings become exact decimals like ledger prices, so valuations are not
+-- computed from amounts and buy prices already rounded to a REAL's precision.
+-- Existing values keep the precision they were stored with.
+
+ALTER TABLE portfolio
+    ALTER COLUMN amount TYPE NUMERIC(20, 6) USING amount::NUMERIC(20, 6),
+    ALTER COLUMN buy_price TYPE NUMERIC(20, 6) USING buy_price::NUMERIC(20, 6);
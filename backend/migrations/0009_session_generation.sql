@@ -0,0 +1,5 @@
+-- Access tokens carry the generation of the account's sessions they were issued
+-- in; revoking every session bumps it, so no clock comparison is involved.
+-- `sessions_revoked_at` stays as a record of when that last happened.
+
+ALTER TABLE account ADD COLUMN session_generation INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,110 @@
+use chrono::NaiveDate;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::services::ledger::EoD;
+
+/// Factors applied to every bar of `ticker` dated before `ex_date`.
+pub struct Adjustment {
+    ticker: String,
+    ex_date: NaiveDate,
+    price: Decimal,
+    volume: Decimal,
+}
+
+impl Adjustment {
+    /// `ratio` new shares per old share: prices are divided by it, volume multiplied.
+    pub fn split(ticker: String, ex_date: NaiveDate, ratio: Decimal) -> Adjustment {
+        Adjustment { ticker, ex_date, price: Decimal::ONE / ratio, volume: ratio }
+    }
+
+    /// Scales earlier prices by `1 - amount / previous_close`, where
+    /// `previous_close` is the raw close of the last trading day before the
+    /// ex-date. Without such a day, or if the dividend is not smaller than that
+    /// close, the dividend cannot be expressed as a factor and is skipped.
+    pub fn dividend(ticker: String, ex_date: NaiveDate, amount: Decimal, previous_close: Option<Decimal>) -> Option<Adjustment> {
+        let previous_close = previous_close.filter(|close| *close > amount)?;
+        Some(Adjustment {
+            ticker,
+            ex_date,
+            price: (previous_close - amount) / previous_close,
+            volume: Decimal::ONE,
+        })
+    }
+
+    pub fn ex_date(&self) -> NaiveDate {
+        self.ex_date
+    }
+}
+
+/// Back-adjusts raw bars so prices before an ex-date are comparable with those
+/// after it; the latest prices stay as they are. Volume only changes with splits.
+/// `adj_close` is replaced with the adjusted close so the series is consistent.
+pub fn adjust(bars: &mut [EoD], adjustments: &[Adjustment]) {
+    for bar in bars {
+        let (mut price, mut volume) = (Decimal::ONE, Decimal::ONE);
+        for adjustment in adjustments.iter().filter(|adjustment| adjustment.ticker == bar.ticker && adjustment.ex_date > bar.date) {
+            price *= adjustment.price;
+            volume *= adjustment.volume;
+        }
+        bar.open = (bar.open * price).round_dp(6);
+        bar.high = (bar.high * price).round_dp(6);
+        bar.low = (bar.low * price).round_dp(6);
+        bar.close = (bar.close * price).round_dp(6);
+        bar.adj_close = bar.close;
+        bar.volume = (Decimal::from(bar.volume) * volume).round().to_i64().unwrap_or(bar.volume);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::services::ledger::bar;
+
+    fn decimal(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn closes_and_volumes(bars: &[EoD]) -> Vec<(String, i64)> {
+        bars.iter().map(|bar| (bar.close.normalize().to_string(), bar.volume)).collect()
+    }
+
+    #[test]
+    fn split_scales_earlier_prices_down_and_volume_up() {
+        let mut bars = vec![bar("A", "2023-01-09", [100; 4], 10), bar("A", "2023-01-10", [51; 4], 20)];
+        adjust(&mut bars, &[Adjustment::split("A".to_string(), date("2023-01-10"), decimal("2"))]);
+        assert_eq!(closes_and_volumes(&bars), vec![("50".to_string(), 20), ("51".to_string(), 20)]);
+        assert_eq!(bars[0].adj_close, bars[0].close);
+    }
+
+    #[test]
+    fn dividend_scales_by_the_previous_close() {
+        let dividend = Adjustment::dividend("A".to_string(), date("2023-01-10"), decimal("1"), Some(decimal("100"))).unwrap();
+        let mut bars = vec![bar("A", "2023-01-06", [50; 4], 10), bar("A", "2023-01-09", [100; 4], 10), bar("A", "2023-01-10", [99; 4], 10)];
+        adjust(&mut bars, &[dividend]);
+        assert_eq!(closes_and_volumes(&bars), vec![("49.5".to_string(), 10), ("99".to_string(), 10), ("99".to_string(), 10)]);
+    }
+
+    #[test]
+    fn dividend_without_a_usable_close_is_skipped() {
+        let ex_date = date("2023-01-10");
+        assert!(Adjustment::dividend("A".to_string(), ex_date, decimal("1"), None).is_none());
+        assert!(Adjustment::dividend("A".to_string(), ex_date, decimal("5"), Some(decimal("5"))).is_none());
+    }
+
+    #[test]
+    fn adjustments_compound_and_only_apply_to_their_ticker() {
+        let adjustments = [
+            Adjustment::split("A".to_string(), date("2023-01-10"), decimal("2")),
+            Adjustment::split("A".to_string(), date("2023-01-20"), decimal("3")),
+        ];
+        let mut bars = vec![bar("A", "2023-01-05", [60; 4], 1), bar("A", "2023-01-15", [30; 4], 2), bar("B", "2023-01-05", [60; 4], 1)];
+        adjust(&mut bars, &adjustments);
+        assert_eq!(closes_and_volumes(&bars), vec![("10".to_string(), 6), ("10".to_string(), 6), ("60".to_string(), 1)]);
+    }
+}
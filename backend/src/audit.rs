@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use utoipa::ToSchema;
+
+/// Kinds of events written to the append-only `audit_log` table.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum AuditAction {
+    #[serde(rename = "login.success")]
+    LoginSucceeded,
+    #[serde(rename = "login.failure")]
+    LoginFailed,
+    #[serde(rename = "account.create")]
+    AccountCreated,
+    #[serde(rename = "account.delete")]
+    AccountDeleted,
+    #[serde(rename = "account.role")]
+    RoleChanged,
+    #[serde(rename = "account.disable")]
+    AccountDisabled,
+    #[serde(rename = "account.enable")]
+    AccountEnabled,
+    #[serde(rename = "portfolio.add")]
+    PortfolioItemAdded,
+    #[serde(rename = "portfolio.alter")]
+    PortfolioItemAltered,
+    #[serde(rename = "portfolio.remove")]
+    PortfolioItemRemoved,
+    #[serde(rename = "watch_list.add")]
+    WatchItemAdded,
+    #[serde(rename = "watch_list.remove")]
+    WatchItemRemoved,
+    #[serde(rename = "ledger.import")]
+    LedgerImported,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::LoginSucceeded => "login.success",
+            AuditAction::LoginFailed => "login.failure",
+            AuditAction::AccountCreated => "account.create",
+            AuditAction::AccountDeleted => "account.delete",
+            AuditAction::RoleChanged => "account.role",
+            AuditAction::AccountDisabled => "account.disable",
+            AuditAction::AccountEnabled => "account.enable",
+            AuditAction::PortfolioItemAdded => "portfolio.add",
+            AuditAction::PortfolioItemAltered => "portfolio.alter",
+            AuditAction::PortfolioItemRemoved => "portfolio.remove",
+            AuditAction::WatchItemAdded => "watch_list.add",
+            AuditAction::WatchItemRemoved => "watch_list.remove",
+            AuditAction::LedgerImported => "ledger.import",
+        }
+    }
+}
+
+/// Appends an entry to the audit log. `actor_id` is the account performing the
+/// action, `subject_id` the account it affects. A failed write is logged rather
+/// than failing the request that triggered it.
+pub async fn record(db: &Pool<Postgres>, action: AuditAction, actor_id: Option<i32>, subject_id: Option<i32>, details: Value) {
+    let result = sqlx::query(
+        "INSERT INTO audit_log (action, actor_id, subject_id, details)
+        VALUES ($1, $2, $3, $4)"
+    )
+    .bind(action.as_str())
+    .bind(actor_id)
+    .bind(subject_id)
+    .bind(&details)
+    .execute(db)
+    .await;
+
+    if let Err(error) = result {
+        log::error!("failed to write audit entry {} {}: {:?}", action.as_str(), details, error);
+    }
+}
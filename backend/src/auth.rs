@@ -0,0 +1,362 @@
+use actix_web::{dev::ServiceRequest, error::Error, http::Method, web::Data, HttpMessage};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use jwt::{SignWithKey, VerifyWithKey};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Pool, Postgres};
+use utoipa::ToSchema;
+
+use crate::{config::AuthConfig, errors::ApiError, roles::Role, AppState};
+
+/// Prefix telling personal API keys apart from JWTs in the `Authorization` header.
+pub const API_KEY_PREFIX: &str = "mpk_";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TokenClaims {
+    pub id: i32,
+    pub role: Role,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+    /// Session generation of the account at issue time; revoking every session
+    /// of the account moves it on and so invalidates the token.
+    pub session_generation: i32,
+    /// Set only for requests authenticated with an API key; JWTs are unrestricted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<ApiScope>>,
+}
+
+/// What a personal API key may be used for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum ApiScope {
+    #[serde(rename = "market:read")]
+    MarketRead,
+    #[serde(rename = "portfolio:read")]
+    PortfolioRead,
+    #[serde(rename = "portfolio:write")]
+    PortfolioWrite,
+}
+
+impl ApiScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiScope::MarketRead => "market:read",
+            ApiScope::PortfolioRead => "portfolio:read",
+            ApiScope::PortfolioWrite => "portfolio:write",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ApiScope> {
+        match value {
+            "market:read" => Some(ApiScope::MarketRead),
+            "portfolio:read" => Some(ApiScope::PortfolioRead),
+            "portfolio:write" => Some(ApiScope::PortfolioWrite),
+            _ => None,
+        }
+    }
+
+    /// Scope an API key needs for the route, or `None` if API keys may not use it at all.
+    fn required_for(method: &Method, path: &str) -> Option<ApiScope> {
+        let read = method == Method::GET;
+        let under = |prefix: &str| path == prefix || path.starts_with(&format!("{}/", prefix));
+        if read && (under("/companies") || under("/ledger") || under("/exchange") || under("/corporate_actions")) {
+            Some(ApiScope::MarketRead)
+        } else if under("/analytics") {
+            // Computed from market data only; POST just carries the request body.
+            Some(ApiScope::MarketRead)
+        } else if read && (under("/portfolio") || under("/watchlist")) {
+            Some(ApiScope::PortfolioRead)
+        } else if !read && (under("/portfolio_item") || under("/watchitem")) {
+            Some(ApiScope::PortfolioWrite)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct StoredApiKey {
+    id: i32,
+    account_id: i32,
+    security_lvl: i32,
+    session_generation: i32,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TokenPair {
+    access_token: String,
+    refresh_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+}
+
+fn jwt_key(config: &AuthConfig) -> Hmac<Sha256> {
+    Hmac::new_from_slice(config.jwt_secret.as_bytes()).unwrap()
+}
+
+pub fn random_string(len: usize) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+    abcdefghijklmnopqrstuvwxyz\
+    0123456789";
+    let mut rng = rand::thread_rng();
+
+    (0..len)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// Refresh tokens are only ever stored as a SHA-256 digest.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn sign_access_token(config: &AuthConfig, id: i32, role: Role, session_generation: i32) -> (String, TokenClaims) {
+    let now = Utc::now();
+    let claims = TokenClaims {
+        id,
+        role,
+        iat: now.timestamp(),
+        exp: (now + Duration::minutes(config.access_token_minutes)).timestamp(),
+        jti: random_string(32),
+        session_generation,
+        scopes: None,
+    };
+    let token_str = claims.clone().sign_with_key(&jwt_key(config)).unwrap();
+    (token_str, claims)
+}
+
+/// Signs a new access token and stores a fresh refresh token for the account.
+pub async fn issue_token_pair(db: &Pool<Postgres>, config: &AuthConfig, id: i32, role: Role) -> Result<TokenPair, sqlx::Error> {
+    let session_generation = sqlx::query_scalar::<_, i32>("SELECT session_generation FROM account WHERE id = $1")
+        .bind(id)
+        .fetch_one(db)
+        .await?;
+    let (access_token, claims) = sign_access_token(config, id, role, session_generation);
+    let refresh_token = random_string(64);
+
+    sqlx::query(
+        "INSERT INTO refresh_token (account_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)"
+    )
+    .bind(id)
+    .bind(hash_token(&refresh_token))
+    .bind(Utc::now() + Duration::days(config.refresh_token_days))
+    .execute(db)
+    .await?;
+
+    Ok(TokenPair {
+        access_token,
+        refresh_token,
+        token_type: "Bearer",
+        expires_in: claims.exp - claims.iat,
+    })
+}
+
+pub async fn revoke_refresh_token(db: &Pool<Postgres>, account_id: i32, refresh_token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE refresh_token SET revoked = TRUE WHERE account_id = $1 AND token_hash = $2")
+        .bind(account_id)
+        .bind(hash_token(refresh_token))
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Adds the token id to the revocation list until the token would have expired anyway.
+/// Entries of tokens that have expired in the meantime are dropped on the way.
+pub async fn revoke_access_token(db: &Pool<Postgres>, claims: &TokenClaims) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM revoked_token WHERE expires_at < now()")
+        .execute(db)
+        .await?;
+    sqlx::query(
+        "INSERT INTO revoked_token (jti, account_id, expires_at)
+        VALUES ($1, $2, to_timestamp($3))
+        ON CONFLICT (jti) DO NOTHING"
+    )
+    .bind(&claims.jti)
+    .bind(claims.id)
+    .bind(claims.exp as f64)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Invalidates every credential issued to the account so far: access tokens by
+/// moving on `session_generation`, refresh tokens and API keys by revoking them.
+pub async fn revoke_all_sessions(db: &Pool<Postgres>, account_id: i32) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query("UPDATE account SET session_generation = session_generation + 1, sessions_revoked_at = now() WHERE id = $1")
+        .bind(account_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("UPDATE refresh_token SET revoked = TRUE WHERE account_id = $1")
+        .bind(account_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("UPDATE api_key SET revoked_at = now() WHERE account_id = $1 AND revoked_at IS NULL")
+        .bind(account_id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await
+}
+
+/// A token counts as revoked if its id is on the revocation list, it was issued
+/// before the account's sessions were revoked, or the account no longer exists
+/// or has been disabled.
+async fn is_revoked(db: &Pool<Postgres>, claims: &TokenClaims) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM revoked_token WHERE jti = $1)
+            OR NOT EXISTS (
+                SELECT 1 FROM account
+                WHERE id = $2
+                AND NOT disabled
+                AND session_generation = $3
+            )"
+    )
+    .bind(&claims.jti)
+    .bind(claims.id)
+    .bind(claims.session_generation)
+    .fetch_one(db)
+    .await
+}
+
+/// Resolves an API key to the same claims a JWT of its account would carry,
+/// restricted to the key's scopes.
+async fn resolve_api_key(db: &Pool<Postgres>, key: &str) -> Result<Option<TokenClaims>, sqlx::Error> {
+    let stored = sqlx::query_as::<_, StoredApiKey>(
+        "SELECT api_key.id, api_key.account_id, account.security_lvl, account.session_generation, api_key.scopes, api_key.created_at, api_key.expires_at
+        FROM api_key JOIN account ON account.id = api_key.account_id
+        WHERE api_key.key_hash = $1
+        AND NOT account.disabled
+        AND api_key.revoked_at IS NULL
+        AND (api_key.expires_at IS NULL OR api_key.expires_at > now())"
+    )
+    .bind(hash_token(key))
+    .fetch_optional(db)
+    .await?;
+
+    let Some(stored) = stored else { return Ok(None) };
+    let Some(role) = Role::from_security_lvl(stored.security_lvl) else { return Ok(None) };
+    sqlx::query("UPDATE api_key SET last_used_at = now() WHERE id = $1")
+        .bind(stored.id)
+        .execute(db)
+        .await?;
+
+    Ok(Some(TokenClaims {
+        id: stored.account_id,
+        role,
+        iat: stored.created_at.timestamp(),
+        exp: stored.expires_at.map(|expires_at| expires_at.timestamp()).unwrap_or(i64::MAX),
+        jti: format!("api_key:{}", stored.id),
+        session_generation: stored.session_generation,
+        scopes: Some(stored.scopes.iter().filter_map(|scope| ApiScope::parse(scope)).collect()),
+    }))
+}
+
+async fn api_key_validator(req: ServiceRequest, key: &str) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let state = req.app_data::<Data<AppState>>().expect("AppState must be registered");
+    match resolve_api_key(&state.db_auth, key).await {
+        Ok(Some(claims)) => {
+            let allowed = ApiScope::required_for(req.method(), req.path())
+                .map(|scope| claims.scopes.iter().flatten().any(|granted| *granted == scope))
+                .unwrap_or(false);
+            if allowed {
+                req.extensions_mut().insert(claims);
+                Ok(req)
+            } else {
+                state.metrics.record_auth_failure("api_key_scope");
+                Err((ApiError::Forbidden("API key scope does not allow this request".to_string()).into(), req))
+            }
+        }
+        Ok(None) => Err((invalid_token(state, "invalid_api_key", "Invalid API key"), req)),
+        Err(error) => Err((ApiError::from(error).into(), req)),
+    }
+}
+
+/// `reason` doubles as the error code of the response.
+fn invalid_token(state: &AppState, reason: &'static str, message: &str) -> Error {
+    state.metrics.record_auth_failure(reason);
+    ApiError::InvalidToken { code: reason, message: message.to_string() }.into()
+}
+
+/// Takes the credentials as an `Option` so a missing or malformed header gets
+/// the same JSON error body as every other failure.
+pub async fn validator(req: ServiceRequest, credentials: Option<BearerAuth>) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let Some(credentials) = credentials else {
+        if let Some(state) = req.app_data::<Data<AppState>>() {
+            state.metrics.record_auth_failure("missing_token");
+        }
+        return Err((ApiError::Unauthorized("Missing or malformed bearer token".to_string()).into(), req));
+    };
+    let token_string = credentials.token();
+    if token_string.starts_with(API_KEY_PREFIX) {
+        return api_key_validator(req, token_string).await;
+    }
+
+    let state = req.app_data::<Data<AppState>>().expect("AppState must be registered");
+    let claims: Result<TokenClaims, &str> = token_string.verify_with_key(&jwt_key(&state.config.auth)).map_err(|_| "Invalid token");
+
+    match claims {
+        Ok(value) if value.exp <= Utc::now().timestamp() => {
+            Err((invalid_token(state, "token_expired", "Token expired"), req))
+        }
+        Ok(value) => {
+            match is_revoked(&state.db_auth, &value).await {
+                Ok(false) => {
+                    req.extensions_mut().insert(value);
+                    Ok(req)
+                }
+                Ok(true) => Err((invalid_token(state, "token_revoked", "Token revoked"), req)),
+                Err(error) => Err((ApiError::from(error).into(), req)),
+            }
+        }
+        Err(_) => Err((invalid_token(state, "invalid_token", "Invalid token"), req)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_key_scope_per_route() {
+        use ApiScope::*;
+        let cases: &[(Method, &str, Option<ApiScope>)] = &[
+            (Method::GET, "/companies", Some(MarketRead)),
+            (Method::GET, "/companies/AAPL/stats", Some(MarketRead)),
+            (Method::GET, "/companiesX", None),
+            (Method::POST, "/companies", None),
+            (Method::GET, "/ledger/AAPL", Some(MarketRead)),
+            (Method::POST, "/ledger/import", None),
+            (Method::GET, "/exchange", Some(MarketRead)),
+            (Method::GET, "/corporate_actions", Some(MarketRead)),
+            (Method::DELETE, "/corporate_actions/1", None),
+            (Method::POST, "/analytics/correlation", Some(MarketRead)),
+            (Method::GET, "/analyticsX", None),
+            (Method::GET, "/portfolio", Some(PortfolioRead)),
+            (Method::GET, "/portfolio/valuation", Some(PortfolioRead)),
+            (Method::POST, "/portfolio", None),
+            (Method::DELETE, "/portfolio", None),
+            (Method::GET, "/watchlist", Some(PortfolioRead)),
+            (Method::POST, "/portfolio_item", Some(PortfolioWrite)),
+            (Method::PATCH, "/portfolio_item", Some(PortfolioWrite)),
+            (Method::DELETE, "/watchitem", Some(PortfolioWrite)),
+            (Method::GET, "/portfolio_item", None),
+            (Method::GET, "/api_keys", None),
+            (Method::POST, "/api_keys", None),
+            (Method::PATCH, "/account/password", None),
+            (Method::GET, "/accounts", None),
+        ];
+        for (method, path, expected) in cases {
+            assert_eq!(ApiScope::required_for(method, path), *expected, "{} {}", method, path);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::password::PasswordPolicy;
+
+/// Default location of the optional TOML file, relative to the working directory.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Server settings, loaded once at startup. Values come from built-in defaults,
+/// then the TOML file named by `CONFIG_FILE` (or `config.toml` if present), then
+/// environment variables, which `.env` may provide. Later sources win.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub password_policy: PasswordPolicy,
+    pub login_throttle: LoginThrottleConfig,
+    pub mailer: MailerConfig,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Number of worker threads, defaults to the number of CPU cores.
+    pub workers: Option<usize>,
+    /// Number of proxies in front of the server that append the address they
+    /// received the request from to `proxy_header`. With 0 the peer address is
+    /// the client's; otherwise the client is that many entries from the right,
+    /// since anything further left was sent by the client itself.
+    pub trusted_proxies: usize,
+    pub proxy_header: ProxyHeader,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 3000,
+            workers: None,
+            trusted_proxies: 0,
+            proxy_header: ProxyHeader::XForwardedFor,
+        }
+    }
+}
+
+/// Connection strings of the four pools; each logs in with a different database role.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub admin_url: String,
+    pub auth_url: String,
+    pub user_url: String,
+    pub moderator_url: String,
+    /// Applies to each pool separately.
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_seconds: u64,
+    pub migrate_on_startup: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            admin_url: String::new(),
+            auth_url: String::new(),
+            user_url: String::new(),
+            moderator_url: String::new(),
+            max_connections: 100,
+            min_connections: 0,
+            acquire_timeout_seconds: 30,
+            migrate_on_startup: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    pub hash_secret: String,
+    pub access_token_minutes: i64,
+    pub refresh_token_days: i64,
+    pub password_reset_minutes: i64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: String::new(),
+            hash_secret: String::new(),
+            access_token_minutes: 15,
+            refresh_token_days: 30,
+            password_reset_minutes: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginThrottleConfig {
+    pub max_failures: u32,
+    pub max_failures_per_ip: u32,
+    pub backoff_seconds: u64,
+    pub lockout_minutes: u64,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        LoginThrottleConfig {
+            max_failures: 5,
+            max_failures_per_ip: 20,
+            backoff_seconds: 1,
+            lockout_minutes: 15,
+        }
+    }
+}
+
+/// Header the trusted proxies record client addresses in.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProxyHeader {
+    XForwardedFor,
+    /// RFC 7239 `Forwarded`, using the `for` parameter of each element.
+    Forwarded,
+}
+
+impl FromStr for ProxyHeader {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "x-forwarded-for" => Ok(ProxyHeader::XForwardedFor),
+            "forwarded" => Ok(ProxyHeader::Forwarded),
+            other => Err(format!("unknown proxy header `{}`, expected `x-forwarded-for` or `forwarded`", other)),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailerKind {
+    Log,
+    File,
+}
+
+impl FromStr for MailerKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "log" => Ok(MailerKind::Log),
+            "file" => Ok(MailerKind::File),
+            other => Err(format!("unknown mailer `{}`, expected `log` or `file`", other)),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailerConfig {
+    pub kind: MailerKind,
+    /// Used by the `file` mailer.
+    pub file: String,
+}
+
+impl Default for MailerConfig {
+    fn default() -> Self {
+        MailerConfig {
+            kind: MailerKind::Log,
+            file: "mail.log".to_string(),
+        }
+    }
+}
+
+/// Collects every problem found while loading so they can be reported together.
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn parse<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = std::env::var(name).ok()?;
+        match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(error) => {
+                self.0.push(format!("{}: cannot parse `{}`: {}", name, value, error));
+                None
+            }
+        }
+    }
+
+    fn env<T>(&mut self, name: &str, target: &mut T)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = self.parse(name) {
+            *target = value;
+        }
+    }
+
+    fn env_opt<T>(&mut self, name: &str, target: &mut Option<T>)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = self.parse(name) {
+            *target = Some(value);
+        }
+    }
+
+    fn check(&mut self, ok: bool, problem: &str) {
+        if !ok {
+            self.0.push(problem.to_string());
+        }
+    }
+}
+
+impl Config {
+    /// Loads and validates the configuration, returning every problem found on failure.
+    pub fn load() -> Result<Config, Vec<String>> {
+        let mut problems = Problems(Vec::new());
+
+        let mut config = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Config::from_file(Path::new(&path)),
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => Config::from_file(Path::new(DEFAULT_CONFIG_FILE)),
+            Err(_) => Ok(Config::default()),
+        }
+        .map_err(|problem| vec![problem])?;
+
+        config.apply_env(&mut problems);
+        config.validate(&mut problems);
+
+        if problems.0.is_empty() {
+            Ok(config)
+        } else {
+            Err(problems.0)
+        }
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("cannot read config file {}: {}", path.display(), error))?;
+        toml::from_str(&contents)
+            .map_err(|error| format!("invalid config file {}: {}", path.display(), error))
+    }
+
+    fn apply_env(&mut self, problems: &mut Problems) {
+        let server = &mut self.server;
+        problems.env("BIND_ADDRESS", &mut server.host);
+        problems.env("PORT", &mut server.port);
+        problems.env_opt("WORKERS", &mut server.workers);
+        problems.env("TRUSTED_PROXIES", &mut server.trusted_proxies);
+        problems.env("PROXY_HEADER", &mut server.proxy_header);
+
+        let database = &mut self.database;
+        problems.env("DATABASE_URL", &mut database.admin_url);
+        problems.env("DATABASE_URL_AUTH", &mut database.auth_url);
+        problems.env("DATABASE_URL_USER", &mut database.user_url);
+        problems.env("DATABASE_URL_MOD", &mut database.moderator_url);
+        problems.env("DATABASE_MAX_CONNECTIONS", &mut database.max_connections);
+        problems.env("DATABASE_MIN_CONNECTIONS", &mut database.min_connections);
+        problems.env("DATABASE_ACQUIRE_TIMEOUT_SECONDS", &mut database.acquire_timeout_seconds);
+        problems.env("MIGRATE_ON_STARTUP", &mut database.migrate_on_startup);
+
+        let auth = &mut self.auth;
+        problems.env("JWT_SECRET", &mut auth.jwt_secret);
+        problems.env("HASH_SECRET", &mut auth.hash_secret);
+        problems.env("ACCESS_TOKEN_MINUTES", &mut auth.access_token_minutes);
+        problems.env("REFRESH_TOKEN_DAYS", &mut auth.refresh_token_days);
+        problems.env("PASSWORD_RESET_MINUTES", &mut auth.password_reset_minutes);
+
+        let policy = &mut self.password_policy;
+        problems.env("PASSWORD_MIN_LENGTH", &mut policy.min_length);
+        problems.env("PASSWORD_REQUIRE_LOWERCASE", &mut policy.require_lowercase);
+        problems.env("PASSWORD_REQUIRE_UPPERCASE", &mut policy.require_uppercase);
+        problems.env("PASSWORD_REQUIRE_DIGIT", &mut policy.require_digit);
+        problems.env("PASSWORD_REQUIRE_SYMBOL", &mut policy.require_symbol);
+
+        let throttle = &mut self.login_throttle;
+        problems.env("LOGIN_MAX_FAILURES", &mut throttle.max_failures);
+        problems.env("LOGIN_MAX_FAILURES_PER_IP", &mut throttle.max_failures_per_ip);
+        problems.env("LOGIN_BACKOFF_SECONDS", &mut throttle.backoff_seconds);
+        problems.env("LOGIN_LOCKOUT_MINUTES", &mut throttle.lockout_minutes);
+
+        problems.env("MAILER", &mut self.mailer.kind);
+        problems.env("MAILER_FILE", &mut self.mailer.file);
+    }
+
+    fn validate(&self, problems: &mut Problems) {
+        for (name, url) in [
+            ("DATABASE_URL", &self.database.admin_url),
+            ("DATABASE_URL_AUTH", &self.database.auth_url),
+            ("DATABASE_URL_USER", &self.database.user_url),
+            ("DATABASE_URL_MOD", &self.database.moderator_url),
+        ] {
+            if url.is_empty() {
+                problems.0.push(format!("{} must be set", name));
+            } else if !(url.starts_with("postgres://") || url.starts_with("postgresql://")) {
+                problems.0.push(format!("{} must be a postgres:// connection string", name));
+            }
+        }
+        problems.check(self.database.max_connections > 0, "DATABASE_MAX_CONNECTIONS must be at least 1");
+        problems.check(self.database.min_connections <= self.database.max_connections, "DATABASE_MIN_CONNECTIONS must not exceed DATABASE_MAX_CONNECTIONS");
+
+        problems.check(!self.server.host.is_empty(), "BIND_ADDRESS must not be empty");
+        problems.check(self.server.workers != Some(0), "WORKERS must be at least 1");
+
+        problems.check(!self.auth.jwt_secret.is_empty(), "JWT_SECRET must be set");
+        problems.check(!self.auth.hash_secret.is_empty(), "HASH_SECRET must be set");
+        problems.check((1..=1440).contains(&self.auth.access_token_minutes), "ACCESS_TOKEN_MINUTES must be between 1 and 1440");
+        problems.check((1..=365).contains(&self.auth.refresh_token_days), "REFRESH_TOKEN_DAYS must be between 1 and 365");
+        problems.check((1..=1440).contains(&self.auth.password_reset_minutes), "PASSWORD_RESET_MINUTES must be between 1 and 1440");
+
+        problems.check(self.password_policy.min_length > 0, "PASSWORD_MIN_LENGTH must be at least 1");
+        problems.check(self.login_throttle.max_failures > 0, "LOGIN_MAX_FAILURES must be at least 1");
+        problems.check(self.login_throttle.max_failures_per_ip > 0, "LOGIN_MAX_FAILURES_PER_IP must be at least 1");
+        problems.check(self.login_throttle.backoff_seconds <= 3600, "LOGIN_BACKOFF_SECONDS must be at most 3600");
+        problems.check((1..=1440).contains(&self.login_throttle.lockout_minutes), "LOGIN_LOCKOUT_MINUTES must be between 1 and 1440");
+        problems.check(self.mailer.kind != MailerKind::File || !self.mailer.file.is_empty(), "MAILER_FILE must be set when MAILER is `file`");
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// How to treat a day on which some tickers traded and others did not.
+#[derive(Deserialize, ToSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingDays {
+    /// Only keep days on which every ticker has a price.
+    #[default]
+    Drop,
+    /// Keep every day any ticker traded, repeating each ticker's previous
+    /// price; days before every ticker has traded once are dropped.
+    ForwardFill,
+}
+
+/// Prices of several series on common dates, one row per series.
+pub struct Aligned {
+    pub dates: Vec<NaiveDate>,
+    pub prices: Vec<Vec<f64>>,
+}
+
+/// Aligns series of `(date, price)` pairs, each in ascending date order.
+pub fn align(series: &[Vec<(NaiveDate, f64)>], missing: MissingDays) -> Aligned {
+    let all_dates: BTreeSet<NaiveDate> = series.iter().flatten().map(|(date, _)| *date).collect();
+    let mut aligned = Aligned { dates: Vec::new(), prices: vec![Vec::new(); series.len()] };
+    let mut cursors = vec![0; series.len()];
+    let mut last: Vec<Option<f64>> = vec![None; series.len()];
+
+    for date in all_dates {
+        let mut traded_by_all = true;
+        for (index, prices) in series.iter().enumerate() {
+            match prices.get(cursors[index]) {
+                Some((day, price)) if *day == date => {
+                    last[index] = Some(*price);
+                    cursors[index] += 1;
+                }
+                _ => traded_by_all = false,
+            }
+        }
+        let keep = match missing {
+            MissingDays::Drop => traded_by_all,
+            MissingDays::ForwardFill => last.iter().all(Option::is_some),
+        };
+        if keep {
+            aligned.dates.push(date);
+            for (row, price) in aligned.prices.iter_mut().zip(&last) {
+                row.push(price.unwrap_or_default());
+            }
+        }
+    }
+    aligned
+}
+
+/// Simple returns between consecutive prices.
+pub fn returns(prices: &[f64]) -> Vec<f64> {
+    prices.windows(2).map(|pair| pair[1] / pair[0] - 1.0).collect()
+}
+
+/// Sample covariance of every pair of equally long series.
+pub fn covariance(series: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let means: Vec<f64> = series.iter().map(|values| values.iter().sum::<f64>() / values.len() as f64).collect();
+    series.iter().zip(&means)
+        .map(|(a, mean_a)| {
+            series.iter().zip(&means)
+                .map(|(b, mean_b)| {
+                    let products: f64 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+                    products / (a.len() as f64 - 1.0)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Pearson correlation from a covariance matrix; `None` where a series does not vary.
+pub fn correlation(covariance: &[Vec<f64>]) -> Vec<Vec<Option<f64>>> {
+    covariance.iter().enumerate()
+        .map(|(i, row)| {
+            row.iter().enumerate()
+                .map(|(j, value)| {
+                    let scale = (covariance[i][i] * covariance[j][j]).sqrt();
+                    (scale > 0.0).then(|| (value / scale).clamp(-1.0, 1.0))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(points: &[(&str, f64)]) -> Vec<(NaiveDate, f64)> {
+        points.iter().map(|(date, price)| (date.parse().unwrap(), *price)).collect()
+    }
+
+    fn dates(aligned: &Aligned) -> Vec<String> {
+        aligned.dates.iter().map(NaiveDate::to_string).collect()
+    }
+
+    fn sample() -> Vec<Vec<(NaiveDate, f64)>> {
+        vec![
+            series(&[("2023-01-02", 1.0), ("2023-01-03", 2.0), ("2023-01-05", 4.0)]),
+            series(&[("2023-01-03", 10.0), ("2023-01-04", 20.0), ("2023-01-05", 30.0)]),
+        ]
+    }
+
+    #[test]
+    fn drop_keeps_common_days_only() {
+        let aligned = align(&sample(), MissingDays::Drop);
+        assert_eq!(dates(&aligned), ["2023-01-03", "2023-01-05"]);
+        assert_eq!(aligned.prices, vec![vec![2.0, 4.0], vec![10.0, 30.0]]);
+    }
+
+    #[test]
+    fn forward_fill_repeats_the_previous_price() {
+        let aligned = align(&sample(), MissingDays::ForwardFill);
+        assert_eq!(dates(&aligned), ["2023-01-03", "2023-01-04", "2023-01-05"]);
+        assert_eq!(aligned.prices, vec![vec![2.0, 2.0, 4.0], vec![10.0, 20.0, 30.0]]);
+    }
+
+    #[test]
+    fn simple_returns() {
+        let simple = returns(&[100.0, 110.0, 99.0]);
+        assert_eq!(simple.len(), 2);
+        assert!((simple[0] - 0.1).abs() < 1e-12 && (simple[1] + 0.1).abs() < 1e-12, "{:?}", simple);
+        assert!(returns(&[100.0]).is_empty());
+    }
+
+    #[test]
+    fn covariance_and_correlation() {
+        let series = [vec![1.0, 2.0, 3.0], vec![2.0, 4.0, 6.0], vec![3.0, 2.0, 1.0], vec![5.0, 5.0, 5.0]];
+        let covariance = covariance(&series);
+        assert_eq!(covariance[0], vec![1.0, 2.0, -1.0, 0.0]);
+        assert_eq!(covariance[1][1], 4.0);
+
+        let correlation = correlation(&covariance);
+        assert_eq!(correlation[0], vec![Some(1.0), Some(1.0), Some(-1.0), None]);
+        assert_eq!(correlation[3], vec![None; 4]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{fmt, time::Duration};
+
+use actix_web::{http::StatusCode, HttpResponse, HttpResponseBuilder, ResponseError};
+use serde::Serialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+/// Error returned by handlers and extractors. Every variant renders as a
+/// `{code, message, details}` JSON body; database and internal errors are
+/// logged server-side and only a generic message reaches the client.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    /// Bearer token rejected by the validator; also sets `WWW-Authenticate`.
+    /// `code` tells clients an expired token, which they can refresh, apart
+    /// from revoked and invalid ones, which need a new login.
+    InvalidToken { code: &'static str, message: String },
+    Forbidden(String),
+    NotFound(String),
+    Unprocessable(String),
+    /// Request body failed validation, `details` lists every problem found.
+    Validation(Vec<String>),
+    TooManyRequests { message: String, retry_after: Duration },
+    Locked { message: String, retry_after: Duration },
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+/// Body of every error response.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Machine readable error code, e.g. `not_found` or `validation_failed`.
+    code: &'static str,
+    message: String,
+    /// Additional context such as validation problems or the violated constraint.
+    #[schema(value_type = Object)]
+    details: Value,
+}
+
+// SQLSTATE codes that describe a client mistake rather than a server failure.
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+const NOT_NULL_VIOLATION: &str = "23502";
+const CHECK_VIOLATION: &str = "23514";
+
+impl ApiError {
+    /// Status, machine readable code, message and details of the response.
+    fn parts(&self) -> (StatusCode, &'static str, String, Value) {
+        match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, "bad_request", message.clone(), Value::Null),
+            ApiError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, "unauthorized", message.clone(), Value::Null),
+            ApiError::InvalidToken { code, message } => (StatusCode::UNAUTHORIZED, code, message.clone(), Value::Null),
+            ApiError::Forbidden(message) => (StatusCode::FORBIDDEN, "forbidden", message.clone(), Value::Null),
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, "not_found", message.clone(), Value::Null),
+            ApiError::Unprocessable(message) => (StatusCode::UNPROCESSABLE_ENTITY, "unprocessable", message.clone(), Value::Null),
+            ApiError::Validation(violations) => (StatusCode::UNPROCESSABLE_ENTITY, "validation_failed", "Request failed validation".to_string(), json!(violations)),
+            ApiError::TooManyRequests { message, retry_after } => (StatusCode::TOO_MANY_REQUESTS, "too_many_requests", message.clone(), json!({ "retry_after": retry_seconds(*retry_after) })),
+            ApiError::Locked { message, retry_after } => (StatusCode::LOCKED, "locked", message.clone(), json!({ "retry_after": retry_seconds(*retry_after) })),
+            ApiError::Database(error) => database_parts(error),
+            ApiError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Internal server error".to_string(), Value::Null),
+        }
+    }
+}
+
+fn retry_seconds(retry_after: Duration) -> u64 {
+    retry_after.as_secs() + 1
+}
+
+fn database_parts(error: &sqlx::Error) -> (StatusCode, &'static str, String, Value) {
+    if let sqlx::Error::RowNotFound = error {
+        return (StatusCode::NOT_FOUND, "not_found", "Resource not found".to_string(), Value::Null);
+    }
+    if let sqlx::Error::Database(db_error) = error {
+        let details = json!({ "constraint": db_error.constraint() });
+        match db_error.code().as_deref() {
+            Some(UNIQUE_VIOLATION) => return (StatusCode::CONFLICT, "conflict", "Resource already exists".to_string(), details),
+            Some(FOREIGN_KEY_VIOLATION) => return (StatusCode::UNPROCESSABLE_ENTITY, "invalid_reference", "Referenced resource does not exist".to_string(), details),
+            Some(NOT_NULL_VIOLATION) | Some(CHECK_VIOLATION) => return (StatusCode::UNPROCESSABLE_ENTITY, "constraint_violation", "Value violates a constraint".to_string(), details),
+            _ => {}
+        }
+    }
+    (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Internal server error".to_string(), Value::Null)
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Database(error) => write!(f, "database error: {}", error),
+            ApiError::Internal(detail) => write!(f, "internal error: {}", detail),
+            other => f.write_str(&other.parts().2),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.parts().0
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let (status, code, message, details) = self.parts();
+        if status.is_server_error() {
+            log::error!("{}", self);
+        } else if let ApiError::Database(error) = self {
+            log::debug!("database error mapped to {}: {}", status, error);
+        }
+
+        let mut response = HttpResponseBuilder::new(status);
+        match self {
+            ApiError::InvalidToken { message, .. } => {
+                response.insert_header(("WWW-Authenticate", format!("Bearer error=\"invalid_token\", error_description=\"{}\"", message)));
+            }
+            ApiError::TooManyRequests { retry_after, .. } | ApiError::Locked { retry_after, .. } => {
+                response.insert_header(("Retry-After", retry_seconds(*retry_after).to_string()));
+            }
+            _ => {}
+        }
+        response.json(ErrorBody { code, message, details })
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(error: sqlx::Error) -> Self {
+        ApiError::Database(error)
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+}
@@ -0,0 +1,418 @@
+use std::{collections::{BTreeMap, BTreeSet}, io::Read, path::Path, str::FromStr};
+
+use actix_web::web;
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord, Trim};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Pool, Postgres, QueryBuilder};
+use utoipa::ToSchema;
+
+use crate::audit::{self, AuditAction};
+use crate::errors::ApiError;
+use crate::services::ledger::{fits_numeric, EoD};
+
+/// Largest CSV body accepted by the upload endpoint.
+pub const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Rows per `INSERT`, eight bind parameters each.
+const BATCH_SIZE: usize = 1000;
+
+/// Only the first rejections are described; `rejected` still counts all of them.
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// Outcome of one imported file.
+#[derive(Serialize, ToSchema, Default)]
+pub struct ImportReport {
+    inserted: u64,
+    updated: u64,
+    rejected: u64,
+    /// Why rows were rejected, prefixed with their line number.
+    errors: Vec<String>,
+}
+
+impl ImportReport {
+    fn reject(&mut self, line: u64, problem: impl std::fmt::Display) {
+        self.rejected += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(format!("line {}: {}", line, problem));
+        }
+    }
+}
+
+/// Positions of the recognised columns in the header row.
+struct Columns {
+    ticker: Option<usize>,
+    date: usize,
+    open: usize,
+    high: usize,
+    low: usize,
+    close: usize,
+    adj_close: Option<usize>,
+    volume: usize,
+}
+
+impl Columns {
+    /// Accepts Yahoo (`Date,Open,High,Low,Close,Adj Close,Volume`) and Stooq
+    /// (`<TICKER>,<PER>,<DATE>,<TIME>,<OPEN>,...,<VOL>`) headers, case-insensitively.
+    /// Unrecognised columns are ignored.
+    fn from_header(header: &StringRecord) -> Result<Columns, ApiError> {
+        let mut found: BTreeMap<&str, usize> = BTreeMap::new();
+        for (index, name) in header.iter().enumerate() {
+            let name: String = name.trim_matches(|c| c == '<' || c == '>')
+                .chars()
+                .filter(|c| !matches!(c, ' ' | '_' | '-'))
+                .collect::<String>()
+                .to_lowercase();
+            let column = match name.as_str() {
+                "ticker" | "symbol" => "ticker",
+                "date" => "date",
+                "open" => "open",
+                "high" => "high",
+                "low" => "low",
+                "close" => "close",
+                "adjclose" => "adj_close",
+                "volume" | "vol" => "volume",
+                _ => continue,
+            };
+            found.entry(column).or_insert(index);
+        }
+
+        let missing: Vec<&str> = ["date", "open", "high", "low", "close", "volume"]
+            .into_iter()
+            .filter(|column| !found.contains_key(column))
+            .collect();
+        if !missing.is_empty() {
+            return Err(ApiError::BadRequest(format!("CSV header is missing {}", missing.join(", "))));
+        }
+        Ok(Columns {
+            ticker: found.get("ticker").copied(),
+            date: found["date"],
+            open: found["open"],
+            high: found["high"],
+            low: found["low"],
+            close: found["close"],
+            adj_close: found.get("adj_close").copied(),
+            volume: found["volume"],
+        })
+    }
+}
+
+/// Upper case ticker without Stooq's `.US` market suffix, so `aapl.us` becomes `AAPL`.
+pub fn normalize_ticker(ticker: &str) -> String {
+    let ticker = ticker.trim().to_uppercase();
+    match ticker.strip_suffix(".US") {
+        Some(stripped) => stripped.to_string(),
+        None => ticker,
+    }
+}
+
+fn field<'r>(record: &'r StringRecord, index: usize, name: &str) -> Result<&'r str, String> {
+    match record.get(index) {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(format!("missing {}", name)),
+    }
+}
+
+/// Rounded to the column's 6 fractional digits; more than 14 integer digits are rejected.
+fn price(record: &StringRecord, index: usize, name: &str) -> Result<Decimal, String> {
+    let value = field(record, index, name)?;
+    let price = Decimal::from_str(value)
+        .map(|price| price.round_dp(6))
+        .map_err(|_| format!("{} `{}` is not a number", name, value))?;
+    if !fits_numeric(price) {
+        return Err(format!("{} `{}` has more than 14 integer digits", name, value));
+    }
+    Ok(price)
+}
+
+fn date(record: &StringRecord, index: usize) -> Result<NaiveDate, String> {
+    let value = field(record, index, "date")?;
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y%m%d"))
+        .map_err(|_| format!("date `{}` is neither YYYY-MM-DD nor YYYYMMDD", value))
+}
+
+/// Converts one row, applying the same rules as the `ledger_bar_check` constraint
+/// so a single bad row cannot abort the whole batch.
+fn parse_row(record: &StringRecord, columns: &Columns, ticker: Option<&str>) -> Result<EoD, String> {
+    let ticker = match (columns.ticker, ticker) {
+        (Some(index), _) => normalize_ticker(field(record, index, "ticker")?),
+        (None, Some(ticker)) => ticker.to_string(),
+        (None, None) => return Err("missing ticker".to_string()),
+    };
+    let close = price(record, columns.close, "close")?;
+    let volume_field = field(record, columns.volume, "volume")?;
+    let volume = Decimal::from_str(volume_field).ok()
+        .and_then(|volume| volume.round().to_i64())
+        .ok_or_else(|| format!("volume `{}` is not a number", volume_field))?;
+    let bar = EoD {
+        ticker,
+        date: date(record, columns.date)?,
+        open: price(record, columns.open, "open")?,
+        high: price(record, columns.high, "high")?,
+        low: price(record, columns.low, "low")?,
+        close,
+        adj_close: match columns.adj_close {
+            Some(index) => price(record, index, "adjusted close")?,
+            None => close,
+        },
+        volume,
+    };
+
+    if bar.low <= Decimal::ZERO || bar.adj_close <= Decimal::ZERO {
+        return Err("prices must be positive".to_string());
+    }
+    if bar.low > bar.open.min(bar.close) || bar.high < bar.open.max(bar.close) {
+        return Err("open and close must lie between low and high".to_string());
+    }
+    if bar.volume < 0 {
+        return Err("volume must not be negative".to_string());
+    }
+    Ok(bar)
+}
+
+/// Accepted rows with the line they were read from, keyed by `(ticker, date)`.
+type Bars = BTreeMap<(String, NaiveDate), (u64, EoD)>;
+
+/// Reads and validates every row, keyed by `(ticker, date)`. A later row for
+/// the same day replaces an earlier one, which counts as rejected.
+fn parse(reader: impl Read, ticker: Option<&str>, report: &mut ImportReport) -> Result<Bars, ApiError> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+    let header = reader.headers()
+        .map_err(|error| ApiError::BadRequest(format!("cannot read CSV header: {}", error)))?;
+    let columns = Columns::from_header(header)?;
+    if columns.ticker.is_none() && ticker.is_none() {
+        return Err(ApiError::BadRequest("CSV has no ticker column, pass the ticker explicitly".to_string()));
+    }
+
+    let mut bars = BTreeMap::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                let line = error.position().map(|position| position.line()).unwrap_or(0);
+                report.reject(line, error);
+                continue;
+            }
+        };
+        let line = record.position().map(|position| position.line()).unwrap_or(0);
+        match parse_row(&record, &columns, ticker) {
+            Ok(bar) => {
+                if let Some((replaced, _)) = bars.insert((bar.ticker.clone(), bar.date), (line, bar)) {
+                    report.reject(replaced, format!("superseded by line {}", line));
+                }
+            }
+            Err(problem) => report.reject(line, problem),
+        }
+    }
+    Ok(bars)
+}
+
+/// Upserts the rows of one CSV file into the ledger on `(ticker, date)`.
+/// Rows are rejected individually for malformed values, inconsistent prices
+/// or tickers missing from `company`; the accepted ones are written in a single
+/// transaction. `ticker` applies to files without a ticker column. Parsing
+/// runs on the blocking thread pool so large files do not stall the workers.
+pub async fn import_csv(db: &Pool<Postgres>, reader: impl Read + Send + 'static, ticker: Option<&str>) -> Result<ImportReport, ApiError> {
+    let ticker = ticker.map(normalize_ticker);
+    let (mut report, mut bars) = web::block(move || {
+        let mut report = ImportReport::default();
+        let bars = parse(reader, ticker.as_deref(), &mut report)?;
+        Ok::<_, ApiError>((report, bars))
+    })
+    .await
+    .map_err(|error| ApiError::Internal(error.to_string()))??;
+
+    let tickers: Vec<String> = bars.keys().map(|(ticker, _)| ticker.clone()).collect::<BTreeSet<_>>().into_iter().collect();
+    let known: BTreeSet<String> = sqlx::query_scalar::<_, String>("SELECT ticker FROM company WHERE ticker = ANY($1)")
+    .bind(&tickers)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .collect();
+    bars.retain(|(ticker, _), (line, _)| {
+        let is_known = known.contains(ticker);
+        if !is_known {
+            report.reject(*line, format!("unknown ticker `{}`", ticker));
+        }
+        is_known
+    });
+
+    let bars: Vec<EoD> = bars.into_values().map(|(_, bar)| bar).collect();
+    let mut tx = db.begin().await?;
+    for batch in bars.chunks(BATCH_SIZE) {
+        let mut upsert: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO ledger (ticker, date, open, high, low, close, adj_close, volume) "
+        );
+        upsert.push_values(batch, |mut row, bar| {
+            row.push_bind(&bar.ticker)
+                .push_bind(bar.date)
+                .push_bind(bar.open)
+                .push_bind(bar.high)
+                .push_bind(bar.low)
+                .push_bind(bar.close)
+                .push_bind(bar.adj_close)
+                .push_bind(bar.volume);
+        });
+        // `xmax` is only zero for rows this statement inserted rather than updated.
+        upsert.push(
+            " ON CONFLICT (ticker, date) DO UPDATE SET
+                open = EXCLUDED.open,
+                high = EXCLUDED.high,
+                low = EXCLUDED.low,
+                close = EXCLUDED.close,
+                adj_close = EXCLUDED.adj_close,
+                volume = EXCLUDED.volume
+            RETURNING (xmax = 0)"
+        );
+        for (inserted,) in upsert.build_query_as::<(bool,)>().fetch_all(&mut tx).await? {
+            if inserted {
+                report.inserted += 1;
+            } else {
+                report.updated += 1;
+            }
+        }
+    }
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// Writes the counts of an import to the audit log.
+pub async fn record_import(db: &Pool<Postgres>, actor_id: Option<i32>, source: &str, report: &ImportReport) {
+    let details = json!({
+        "source": source,
+        "inserted": report.inserted,
+        "updated": report.updated,
+        "rejected": report.rejected,
+    });
+    audit::record(db, AuditAction::LedgerImported, actor_id, None, details).await;
+}
+
+/// `backend import [--ticker TICKER] FILE...`. Files without a ticker column
+/// take the ticker from `--ticker`, or else from the file name, so Stooq's
+/// `aapl.us.txt` and Yahoo's `AAPL.csv` both import as `AAPL`.
+pub async fn run_cli(db: &Pool<Postgres>, args: Vec<String>) -> std::io::Result<()> {
+    let mut ticker: Option<String> = None;
+    let mut files: Vec<String> = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--ticker" {
+            ticker = Some(args.next().ok_or_else(|| std::io::Error::other("--ticker needs a value"))?);
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        return Err(std::io::Error::other("usage: backend import [--ticker TICKER] FILE..."));
+    }
+
+    for file in files {
+        let path = Path::new(&file);
+        let file_ticker = ticker.clone()
+            .or_else(|| path.file_stem()?.to_str().map(str::to_string));
+        let reader = std::fs::File::open(path)?;
+        let report = import_csv(db, reader, file_ticker.as_deref())
+            .await
+            .map_err(|error| std::io::Error::other(format!("{}: {}", file, error)))?;
+        record_import(db, None, &file, &report).await;
+        println!("{}: {} inserted, {} updated, {} rejected", file, report.inserted, report.updated, report.rejected);
+        for problem in &report.errors {
+            println!("  {}", problem);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(csv: &str, ticker: Option<&str>) -> Result<(ImportReport, Bars), ApiError> {
+        let mut report = ImportReport::default();
+        let bars = parse(csv.as_bytes(), ticker, &mut report)?;
+        Ok((report, bars))
+    }
+
+    fn closes(bars: &Bars) -> Vec<(String, String, String)> {
+        bars.values()
+            .map(|(_, bar)| (bar.ticker.clone(), bar.date.to_string(), bar.close.normalize().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn yahoo_file() {
+        let (report, bars) = import(
+            "Date,Open,High,Low,Close,Adj Close,Volume\n\
+             2023-01-03,130.28,130.9,124.17,125.07,124.22,112117500\n",
+            Some("AAPL"),
+        ).unwrap();
+        assert_eq!(report.rejected, 0);
+        let (_, bar) = &bars[&("AAPL".to_string(), "2023-01-03".parse().unwrap())];
+        assert_eq!(bar.adj_close, Decimal::from_str("124.22").unwrap());
+        assert_eq!(bar.volume, 112117500);
+    }
+
+    #[test]
+    fn stooq_file_with_compact_dates_and_market_suffix() {
+        let (report, bars) = import(
+            "<TICKER>,<PER>,<DATE>,<TIME>,<OPEN>,<HIGH>,<LOW>,<CLOSE>,<VOL>,<OPENINT>\n\
+             aapl.us,D,20230103,000000,130.28,130.9,124.17,125.07,112117500,0\n\
+             MSFT.US,D,20230103,000000,243.08,245.75,237.4,239.58,25740036,0\n",
+            None,
+        ).unwrap();
+        assert_eq!(report.rejected, 0);
+        assert_eq!(closes(&bars), vec![
+            ("AAPL".to_string(), "2023-01-03".to_string(), "125.07".to_string()),
+            ("MSFT".to_string(), "2023-01-03".to_string(), "239.58".to_string()),
+        ]);
+        // Without an adjusted close column the close is taken as is.
+        assert!(bars.values().all(|(_, bar)| bar.adj_close == bar.close));
+    }
+
+    #[test]
+    fn ticker_suffix_is_stripped() {
+        assert_eq!(normalize_ticker(" aapl.us "), "AAPL");
+        assert_eq!(normalize_ticker("BRK.B"), "BRK.B");
+    }
+
+    #[test]
+    fn missing_columns_reject_the_file() {
+        let error = import("Date,Open,High,Close\n2023-01-03,1,2,1\n", Some("AAPL")).err().unwrap();
+        assert!(matches!(error, ApiError::BadRequest(message) if message == "CSV header is missing low, volume"));
+        assert!(import("Date,Open,High,Low,Close,Volume\n", None).is_err());
+    }
+
+    #[test]
+    fn inconsistent_rows_are_rejected_individually() {
+        let (report, bars) = import(
+            "Date,Open,High,Low,Close,Volume\n\
+             2023-01-02,10,12,11,11,100\n\
+             2023-01-03,10,12,9,11,-5\n\
+             03/01/2023,10,12,9,11,100\n\
+             2023-01-05,10,12,9,11,100\n",
+            Some("AAPL"),
+        ).unwrap();
+        assert_eq!(report.rejected, 3);
+        assert_eq!(report.errors, vec![
+            "line 2: open and close must lie between low and high".to_string(),
+            "line 3: volume must not be negative".to_string(),
+            "line 4: date `03/01/2023` is neither YYYY-MM-DD nor YYYYMMDD".to_string(),
+        ]);
+        assert_eq!(closes(&bars), vec![("AAPL".to_string(), "2023-01-05".to_string(), "11".to_string())]);
+    }
+
+    #[test]
+    fn later_duplicate_supersedes_earlier_row() {
+        let (report, bars) = import(
+            "Ticker,Date,Open,High,Low,Close,Volume\n\
+             AAPL,2023-01-03,10,12,9,11,100\n\
+             aapl,2023-01-03,10,12,9,12,100\n",
+            None,
+        ).unwrap();
+        assert_eq!(report.rejected, 1);
+        assert_eq!(report.errors, vec!["line 2: superseded by line 3".to_string()]);
+        assert_eq!(closes(&bars), vec![("AAPL".to_string(), "2023-01-03".to_string(), "12".to_string())]);
+    }
+}
@@ -0,0 +1,277 @@
+//! Technical indicators over a price series, oldest value first. Every function
+//! returns one entry per input value, `None` until enough history has been seen.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndicatorName {
+    /// Simple moving average, `params=period` (20).
+    Sma,
+    /// Exponential moving average, `params=period` (20).
+    Ema,
+    /// Relative strength index with Wilder's smoothing, `params=period` (14).
+    Rsi,
+    /// Moving average convergence divergence, `params=fast,slow,signal` (12,26,9).
+    Macd,
+    /// Bollinger bands, `params=period,deviations` (20,2).
+    Bollinger,
+}
+
+/// An indicator with validated parameters.
+#[derive(Clone, Copy, Debug)]
+pub enum Indicator {
+    Sma { period: usize },
+    Ema { period: usize },
+    Rsi { period: usize },
+    Macd { fast: usize, slow: usize, signal: usize },
+    Bollinger { period: usize, deviations: f64 },
+}
+
+impl Indicator {
+    /// Builds the indicator from comma separated `params`, using the defaults
+    /// listed on `IndicatorName` for missing ones.
+    pub fn new(name: IndicatorName, params: Option<&str>) -> Result<Indicator, String> {
+        let params: Vec<&str> = params
+            .map(|params| params.split(',').map(str::trim).filter(|param| !param.is_empty()).collect())
+            .unwrap_or_default();
+        let expected = match name {
+            IndicatorName::Sma | IndicatorName::Ema | IndicatorName::Rsi => 1,
+            IndicatorName::Macd => 3,
+            IndicatorName::Bollinger => 2,
+        };
+        if params.len() > expected {
+            return Err(format!("expected at most {} parameters, got {}", expected, params.len()));
+        }
+        let period = |index: usize, default: usize| -> Result<usize, String> {
+            match params.get(index) {
+                None => Ok(default),
+                Some(param) => match param.parse::<usize>() {
+                    Ok(period) if period > 0 => Ok(period),
+                    _ => Err(format!("`{}` is not a positive whole number", param)),
+                },
+            }
+        };
+
+        let indicator = match name {
+            IndicatorName::Sma => Indicator::Sma { period: period(0, 20)? },
+            IndicatorName::Ema => Indicator::Ema { period: period(0, 20)? },
+            IndicatorName::Rsi => Indicator::Rsi { period: period(0, 14)? },
+            IndicatorName::Macd => {
+                let (fast, slow, signal) = (period(0, 12)?, period(1, 26)?, period(2, 9)?);
+                if fast >= slow {
+                    return Err("the fast period must be shorter than the slow one".to_string());
+                }
+                Indicator::Macd { fast, slow, signal }
+            }
+            IndicatorName::Bollinger => {
+                let deviations = match params.get(1) {
+                    None => 2.0,
+                    Some(param) => match param.parse::<f64>() {
+                        Ok(deviations) if deviations > 0.0 && deviations.is_finite() => deviations,
+                        _ => return Err(format!("`{}` is not a positive number", param)),
+                    },
+                };
+                Indicator::Bollinger { period: period(0, 20)?, deviations }
+            }
+        };
+        Ok(indicator)
+    }
+
+    /// Names of the values computed for each point, in the order `compute` returns them.
+    pub fn outputs(&self) -> &'static [&'static str] {
+        match self {
+            Indicator::Sma { .. } | Indicator::Ema { .. } | Indicator::Rsi { .. } => &["value"],
+            Indicator::Macd { .. } => &["macd", "signal", "histogram"],
+            Indicator::Bollinger { .. } => &["middle", "upper", "lower"],
+        }
+    }
+
+    pub fn compute(&self, values: &[f64]) -> Vec<Option<Vec<f64>>> {
+        let single = |series: Vec<Option<f64>>| series.into_iter().map(|value| value.map(|value| vec![value])).collect();
+        let triple = |series: Vec<Option<(f64, f64, f64)>>| series.into_iter().map(|value| value.map(|(a, b, c)| vec![a, b, c])).collect();
+        match *self {
+            Indicator::Sma { period } => single(sma(values, period)),
+            Indicator::Ema { period } => single(ema(values, period)),
+            Indicator::Rsi { period } => single(rsi(values, period)),
+            Indicator::Macd { fast, slow, signal } => triple(macd(values, fast, slow, signal)),
+            Indicator::Bollinger { period, deviations } => triple(bollinger(values, period, deviations)),
+        }
+    }
+}
+
+pub fn sma(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    let mut sum = 0.0;
+    for (index, value) in values.iter().enumerate() {
+        sum += value;
+        if index >= period {
+            sum -= values[index - period];
+        }
+        if index + 1 >= period {
+            result[index] = Some(sum / period as f64);
+        }
+    }
+    result
+}
+
+/// Seeded with the simple average of the first `period` values.
+pub fn ema(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    if values.len() < period {
+        return result;
+    }
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut average = values[..period].iter().sum::<f64>() / period as f64;
+    result[period - 1] = Some(average);
+    for (index, value) in values.iter().enumerate().skip(period) {
+        average += alpha * (value - average);
+        result[index] = Some(average);
+    }
+    result
+}
+
+pub fn rsi(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    if values.len() <= period {
+        return result;
+    }
+    let change = |index: usize| values[index] - values[index - 1];
+    // Without any movement there is neither strength nor weakness.
+    let index_of = |gain: f64, loss: f64| match (gain == 0.0, loss == 0.0) {
+        (true, true) => 50.0,
+        (false, true) => 100.0,
+        _ => 100.0 - 100.0 / (1.0 + gain / loss),
+    };
+
+    let (mut gain, mut loss) = (1..=period).fold((0.0, 0.0), |(gain, loss), index| {
+        let change = change(index);
+        (gain + change.max(0.0), loss + (-change).max(0.0))
+    });
+    gain /= period as f64;
+    loss /= period as f64;
+    result[period] = Some(index_of(gain, loss));
+    for (index, slot) in result.iter_mut().enumerate().skip(period + 1) {
+        let change = change(index);
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+        *slot = Some(index_of(gain, loss));
+    }
+    result
+}
+
+/// MACD line, its signal line and their difference.
+pub fn macd(values: &[f64], fast: usize, slow: usize, signal: usize) -> Vec<Option<(f64, f64, f64)>> {
+    let mut result = vec![None; values.len()];
+    let (fast, slow) = (ema(values, fast), ema(values, slow));
+    // The MACD line exists from the first slow average on; the signal is an EMA over that part.
+    let start = slow.iter().position(Option::is_some).unwrap_or(values.len());
+    let line: Vec<f64> = fast[start..].iter().zip(&slow[start..])
+        .map(|(fast, slow)| fast.unwrap_or_default() - slow.unwrap_or_default())
+        .collect();
+    for (offset, signal) in ema(&line, signal).into_iter().enumerate() {
+        if let Some(signal) = signal {
+            result[start + offset] = Some((line[offset], signal, line[offset] - signal));
+        }
+    }
+    result
+}
+
+/// Middle band, upper and lower band `deviations` population standard deviations away.
+pub fn bollinger(values: &[f64], period: usize, deviations: f64) -> Vec<Option<(f64, f64, f64)>> {
+    sma(values, period).into_iter().enumerate()
+        .map(|(index, middle)| {
+            let middle = middle?;
+            let window = &values[index + 1 - period..=index];
+            let variance = window.iter().map(|value| (value - middle).powi(2)).sum::<f64>() / period as f64;
+            let width = deviations * variance.sqrt();
+            Some((middle, middle + width, middle - width))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[Option<f64>], expected: &[Option<f64>]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} != {:?}", actual, expected);
+        for (actual_value, expected_value) in actual.iter().zip(expected) {
+            match (actual_value, expected_value) {
+                (Some(a), Some(e)) => assert!((a - e).abs() < 1e-9, "{:?} != {:?}", actual, expected),
+                (None, None) => {}
+                _ => panic!("{:?} != {:?}", actual, expected),
+            }
+        }
+    }
+
+    fn column(series: &[Option<(f64, f64, f64)>], pick: fn(&(f64, f64, f64)) -> f64) -> Vec<Option<f64>> {
+        series.iter().map(|value| value.as_ref().map(pick)).collect()
+    }
+
+    #[test]
+    fn sma_averages_the_trailing_window() {
+        assert_close(&sma(&[1.0, 2.0, 3.0, 4.0, 5.0], 3), &[None, None, Some(2.0), Some(3.0), Some(4.0)]);
+        assert_close(&sma(&[1.0, 2.0], 1), &[Some(1.0), Some(2.0)]);
+    }
+
+    #[test]
+    fn ema_is_seeded_with_the_simple_average() {
+        // alpha = 2 / (3 + 1) = 0.5
+        assert_close(&ema(&[1.0, 2.0, 3.0, 4.0, 6.0], 3), &[None, None, Some(2.0), Some(3.0), Some(4.5)]);
+    }
+
+    #[test]
+    fn period_longer_than_the_series_yields_no_values() {
+        let values = [1.0, 2.0, 3.0];
+        assert_close(&sma(&values, 4), &[None; 3]);
+        assert_close(&ema(&values, 4), &[None; 3]);
+        assert_close(&rsi(&values, 3), &[None; 3]);
+        assert!(macd(&values, 2, 4, 2).iter().all(Option::is_none));
+        assert!(bollinger(&values, 4, 2.0).iter().all(Option::is_none));
+        assert!(sma(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn rsi_uses_wilder_smoothing() {
+        // Changes +1, -1, +1: averages 0.5 / 0.5, then 0.75 / 0.25.
+        assert_close(&rsi(&[1.0, 2.0, 1.0, 2.0], 2), &[None, None, Some(50.0), Some(75.0)]);
+    }
+
+    #[test]
+    fn rsi_at_the_extremes() {
+        assert_close(&rsi(&[5.0; 4], 2), &[None, None, Some(50.0), Some(50.0)]);
+        assert_close(&rsi(&[1.0, 2.0, 3.0, 4.0], 2), &[None, None, Some(100.0), Some(100.0)]);
+        assert_close(&rsi(&[4.0, 3.0, 2.0, 1.0], 2), &[None, None, Some(0.0), Some(0.0)]);
+    }
+
+    #[test]
+    fn macd_signal_starts_after_the_slow_average() {
+        // Fast EMA(1) is the series itself, slow EMA(2) trails it by 0.5 on a steady rise.
+        let result = macd(&[1.0, 2.0, 3.0, 4.0], 1, 2, 2);
+        assert_close(&column(&result, |value| value.0), &[None, None, Some(0.5), Some(0.5)]);
+        assert_close(&column(&result, |value| value.1), &[None, None, Some(0.5), Some(0.5)]);
+        assert_close(&column(&result, |value| value.2), &[None, None, Some(0.0), Some(0.0)]);
+    }
+
+    #[test]
+    fn bollinger_bands_use_the_population_deviation() {
+        let width = 2.0 * (2.0f64 / 3.0).sqrt();
+        let result = bollinger(&[1.0, 2.0, 3.0, 5.0], 3, 2.0);
+        assert_close(&column(&result, |value| value.0), &[None, None, Some(2.0), Some(10.0 / 3.0)]);
+        assert_close(&column(&result, |value| value.1)[..3], &[None, None, Some(2.0 + width)]);
+        assert_close(&column(&result, |value| value.2)[..3], &[None, None, Some(2.0 - width)]);
+    }
+
+    #[test]
+    fn parameters_fall_back_to_defaults_and_are_validated() {
+        assert!(matches!(Indicator::new(IndicatorName::Sma, None), Ok(Indicator::Sma { period: 20 })));
+        assert!(matches!(Indicator::new(IndicatorName::Macd, Some("5")), Ok(Indicator::Macd { fast: 5, slow: 26, signal: 9 })));
+        assert!(matches!(Indicator::new(IndicatorName::Bollinger, Some("10, 1.5")), Ok(Indicator::Bollinger { period: 10, .. })));
+        assert!(Indicator::new(IndicatorName::Rsi, Some("0")).is_err());
+        assert!(Indicator::new(IndicatorName::Ema, Some("5,6")).is_err());
+        assert!(Indicator::new(IndicatorName::Macd, Some("26,12")).is_err());
+        assert!(Indicator::new(IndicatorName::Bollinger, Some("20,-1")).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use chrono::Utc;
+
+use crate::config::{MailerConfig, MailerKind};
+
+/// Delivers account e-mails such as password reset tokens. Accounts are addressed
+/// by login, which `POST /account` only accepts in the form of an e-mail address.
+pub trait Mailer: Send + Sync {
+    fn send(&self, to: &str, subject: &str, body: &str) -> std::io::Result<()>;
+}
+
+/// Writes mails to the server log; meant for local development.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, to: &str, subject: &str, body: &str) -> std::io::Result<()> {
+        log::info!("mail to {}: {}\n{}", to, subject, body);
+        Ok(())
+    }
+}
+
+/// Appends mails to a file, one block per message.
+pub struct FileMailer {
+    path: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(path: impl Into<PathBuf>) -> FileMailer {
+        FileMailer { path: path.into() }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, to: &str, subject: &str, body: &str) -> std::io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "Date: {}\nTo: {}\nSubject: {}\n\n{}\n", Utc::now().to_rfc2822(), to, subject, body)
+    }
+}
+
+/// Builds the mailer selected in the configuration.
+pub fn from_config(config: &MailerConfig) -> Box<dyn Mailer> {
+    match config.kind {
+        MailerKind::File => Box::new(FileMailer::new(&config.file)),
+        MailerKind::Log => Box::new(LogMailer),
+    }
+}
@@ -1,115 +1,228 @@
-use actix_web::{web, App, HttpServer, dev::ServiceRequest, HttpMessage, error::Error};
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use actix_web::{dev::Service, web, App, HttpServer};
+use std::time::{Duration, Instant};
+
+use sqlx::{migrate::Migrator, postgres::PgPoolOptions, Pool, Postgres};
 use dotenv::dotenv;
-use actix_web_httpauth::{extractors::{bearer::{self, BearerAuth}, AuthenticationError}, middleware::HttpAuthentication};
-use hmac::{Hmac, Mac};
-use jwt::VerifyWithKey;
-use serde::{Deserialize, Serialize};
-use sha2::Sha256;
+use actix_web_httpauth::middleware::HttpAuthentication;
 
+mod adjust;
+mod audit;
+mod auth;
+mod config;
+mod correlation;
+mod errors;
+mod import;
+mod indicators;
+mod mailer;
+mod metrics;
+mod openapi;
+mod pagination;
+mod password;
+mod resample;
+mod roles;
 mod services;
+mod stats;
+mod throttle;
+use auth::{validator, TokenClaims};
+use config::{Config, DatabaseConfig};
+use errors::ApiError;
 use services::accounts;
+use services::analytics;
 use services::companies;
+use services::corporate_actions;
 use services::ledger;
 use services::watch_list;
 use services::portfolio;
 use services::exchange;
+use services::api_keys;
+use services::audit_log;
+use services::docs;
+use services::health;
+#[cfg(feature = "test-routes")]
+use services::test_routes;
 
 pub struct AppState {
     db_auth: Pool<Postgres>,
     db_user: Pool<Postgres>,
     db_moderator: Pool<Postgres>,
     db_admin: Pool<Postgres>,
+    config: Config,
+    mailer: Box<dyn mailer::Mailer>,
+    login_throttle: throttle::LoginThrottle,
+    metrics: metrics::Metrics,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct TokenClaims {
-    id: i32,
-    security_lvl: i32,
+impl AppState {
+    /// Every pool, named as in readiness checks and metrics.
+    fn pools(&self) -> [(&'static str, &Pool<Postgres>); 4] {
+        [
+            ("admin", &self.db_admin),
+            ("auth", &self.db_auth),
+            ("user", &self.db_user),
+            ("moderator", &self.db_moderator),
+        ]
+    }
 }
 
-async fn validator(req: ServiceRequest, credentials: BearerAuth) -> Result<ServiceRequest, (Error, ServiceRequest)>{
-    let jwt_secret: String = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let key: Hmac<Sha256> = Hmac::new_from_slice(jwt_secret.as_bytes()).unwrap();
- 
-    let token_string = credentials.token();
+async fn connect(config: &DatabaseConfig, url: &str) -> Pool<Postgres> {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_seconds))
+        .connect(url)
+        .await
+        .expect("Error building a connection pool")
+}
 
-    let claims: Result<TokenClaims, &str> = token_string.verify_with_key(&key).map_err(|_| "Invalid token");
+/// Schema migrations in `backend/migrations`, embedded at compile time.
+static MIGRATOR: Migrator = sqlx::migrate!();
 
-    match claims {
-        Ok(value) => {
-            req.extensions_mut().insert(value);
-            Ok(req)
-        }
-        Err(_) => {
-            let config = req.app_data::<bearer::Config>().cloned().unwrap_or_default().scope("");
-            Err((AuthenticationError::from(config).into(), req))
-        }
-    }
+async fn run_migrations(pool: &Pool<Postgres>) -> std::io::Result<()> {
+    MIGRATOR
+        .run(pool)
+        .await
+        .map_err(|error| std::io::Error::other(format!("Error running migrations: {}", error)))?;
+    log::info!("database schema is up to date");
+    Ok(())
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let database_admin_url = std::env::var("DATABASE_URL").expect("Database url must be set");
-    let database_auth_url = std::env::var("DATABASE_URL_AUTH").expect("Database url must be set");
-    let database_user_url = std::env::var("DATABASE_URL_USER").expect("Database url must be set");
-    let database_mod_url = std::env::var("DATABASE_URL_MOD").expect("Database url must be set");
+    env_logger::init();
+    let config = Config::load().unwrap_or_else(|problems| {
+        eprintln!("Invalid configuration:");
+        for problem in problems {
+            eprintln!("  - {}", problem);
+        }
+        std::process::exit(1);
+    });
 
-    let pool_admin= PgPoolOptions::new()
-        .max_connections(100)
-        .connect(&database_admin_url)
-        .await
-        .expect("Error building a connection pool");
+    let pool_admin = connect(&config.database, &config.database.admin_url).await;
 
-    let pool_auth= PgPoolOptions::new()
-        .max_connections(100)
-        .connect(&database_auth_url)
-        .await
-        .expect("Error building a connection pool");
+    // `backend migrate` only applies pending migrations, which also creates the
+    // roles the other pools log in through. MIGRATE_ON_STARTUP does the same before serving.
+    if std::env::args().nth(1).as_deref() == Some("migrate") {
+        return run_migrations(&pool_admin).await;
+    }
+    // `backend import [--ticker TICKER] FILE...` loads CSV prices into the ledger and exits.
+    if std::env::args().nth(1).as_deref() == Some("import") {
+        return import::run_cli(&pool_admin, std::env::args().skip(2).collect()).await;
+    }
+    if config.database.migrate_on_startup {
+        run_migrations(&pool_admin).await?;
+    }
 
-    let pool_user = PgPoolOptions::new()
-        .max_connections(100)
-        .connect(&database_user_url)
-        .await
-        .expect("Error building a connection pool");
+    let pool_auth = connect(&config.database, &config.database.auth_url).await;
+    let pool_user = connect(&config.database, &config.database.user_url).await;
+    let pool_mod = connect(&config.database, &config.database.moderator_url).await;
+    let bind_address = (config.server.host.clone(), config.server.port);
+    let workers = config.server.workers;
 
-    let pool_mod = PgPoolOptions::new()
-        .max_connections(100)
-        .connect(&database_mod_url)
-        .await
-        .expect("Error building a connection pool");
+    // Shared by all workers so login throttling counts attempts across the whole server.
+    let state = web::Data::new(AppState {
+        db_user: pool_user,
+        db_admin: pool_admin,
+        db_auth: pool_auth,
+        db_moderator: pool_mod,
+        mailer: mailer::from_config(&config.mailer),
+        login_throttle: throttle::LoginThrottle::new(&config.login_throttle),
+        metrics: metrics::Metrics::default(),
+        config,
+    });
 
-    HttpServer::new(move || {
-        let bearer_middleware = HttpAuthentication::bearer(validator);
-        App::new()
-            .app_data(web::Data::new(AppState {db_user: pool_user.clone(), db_admin: pool_admin.clone(), db_auth: pool_auth.clone(), db_moderator: pool_mod.clone()}))
-            .service(accounts::fetch_acconts)
+    let server = HttpServer::new(move || {
+        let bearer_middleware = HttpAuthentication::with_fn(validator);
+        let app = App::new()
+            .app_data(state.clone())
+            .wrap_fn(|req, srv| {
+                let started = Instant::now();
+                let method = req.method().to_string();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await?;
+                    let route = response.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+                    if let Some(state) = response.request().app_data::<web::Data<AppState>>() {
+                        state.metrics.record_request(&method, &route, response.status().as_u16(), started.elapsed());
+                    }
+                    Ok(response)
+                }
+            })
+            .app_data(web::JsonConfig::default().error_handler(|error, _| ApiError::BadRequest(error.to_string()).into()))
+            .app_data(web::QueryConfig::default().error_handler(|error, _| ApiError::BadRequest(error.to_string()).into()))
+            .app_data(web::PathConfig::default().error_handler(|error, _| ApiError::BadRequest(error.to_string()).into()))
+            .service(health::healthz)
+            .service(health::readyz)
+            .service(health::metrics)
+            .service(docs::openapi_json)
+            .service(docs::swagger_ui)
+            .service(docs::redoc)
             .service(accounts::create_account)
             .service(accounts::basic_auth)
-            .service(companies::fetch_comp_test)
-            .service(ledger::fetch_ledg_test)
-            .service(watch_list::fetch_watch_test)
-            .service(portfolio::fetch_portfolio_test)
+            .service(accounts::refresh_token)
+            .service(accounts::request_password_reset)
+            .service(accounts::confirm_password_reset);
+        #[cfg(feature = "test-routes")]
+        let app = app
+            .service(test_routes::fetch_comp_test)
+            .service(test_routes::fetch_ledg_test)
+            .service(test_routes::fetch_watch_test)
+            .service(test_routes::fetch_portfolio_test);
+        app
             .service(
                 web::scope("")
                     .wrap(bearer_middleware)
                     .service(companies::fetch_companies)
+                    // Before `/companies/{ticker}`, which would otherwise take "search" for a ticker.
+                    .service(companies::search_companies)
                     .service(ledger::fetch_ledger)
+                    // Built by hand so the raised body limit applies to this route alone.
+                    .service(
+                        web::resource("/ledger/import")
+                            .app_data(web::PayloadConfig::new(import::MAX_UPLOAD_BYTES))
+                            .route(web::post().to(ledger::import_ledger))
+                    )
                     .service(watch_list::post_watchitem)
                     .service(watch_list::fetch_watch_list)
                     .service(watch_list::delete_watch_item)
                     .service(portfolio::fetch_portfolio)
+                    .service(portfolio::fetch_portfolio_valuation)
                     .service(portfolio::post_portfolio_item)
                     .service(portfolio::delete_portfolio_item)
                     .service(portfolio::alter_portfolio_item)
                     .service(exchange::fetch_exchange)
                     .service(ledger::fetch_ledger_by_ticker)
+                    .service(ledger::fetch_indicators)
                     .service(companies::fetch_companies_by_ticker)
+                    .service(companies::fetch_company_stats)
+                    .service(corporate_actions::fetch_corporate_actions)
+                    .service(corporate_actions::create_corporate_action)
+                    .service(corporate_actions::update_corporate_action)
+                    .service(corporate_actions::delete_corporate_action)
+                    .service(accounts::fetch_acconts)
+                    .service(accounts::revoke_account_sessions)
+                    .service(accounts::unlock_account)
+                    .service(accounts::change_account_role)
+                    .service(accounts::disable_account)
+                    .service(accounts::enable_account)
+                    .service(accounts::change_password)
+                    .service(accounts::fetch_account_export)
                     .service(accounts::delete_account)
+                    .service(accounts::logout)
+                    .service(api_keys::create_api_key)
+                    .service(api_keys::fetch_api_keys)
+                    .service(api_keys::revoke_api_key)
+                    .service(audit_log::fetch_audit_log)
+                    .service(analytics::correlation_matrix)
             )
-    })
-    .bind(("127.0.0.1", 3000))?
-    .run()
-    .await
+    });
+    let server = match workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    log::info!("listening on {}:{}", bind_address.0, bind_address.1);
+    server
+        .bind(bind_address)?
+        .run()
+        .await
 }
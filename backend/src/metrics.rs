@@ -0,0 +1,99 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use sqlx::{Pool, Postgres};
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Requests served for one method and route pattern.
+#[derive(Default)]
+struct RouteStats {
+    by_status: BTreeMap<u16, u64>,
+    /// Cumulative counts per entry of `LATENCY_BUCKETS`.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum_seconds: f64,
+}
+
+/// In-memory counters rendered in the Prometheus text exposition format.
+/// Routes are keyed by their pattern (`/ledger/{ticker}`), not the concrete
+/// path, so the number of series stays bounded.
+#[derive(Default)]
+pub struct Metrics {
+    routes: Mutex<BTreeMap<(String, String), RouteStats>>,
+    auth_failures: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn record_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut routes = self.routes.lock().unwrap();
+        let stats = routes.entry((method.to_string(), route.to_string())).or_default();
+        *stats.by_status.entry(status).or_default() += 1;
+        for (bucket, bound) in stats.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        stats.count += 1;
+        stats.sum_seconds += seconds;
+    }
+
+    /// Counts a rejected login or bearer credential, `reason` becomes a label value.
+    pub fn record_auth_failure(&self, reason: &'static str) {
+        *self.auth_failures.lock().unwrap().entry(reason).or_default() += 1;
+    }
+
+    /// Renders every metric, sampling pool utilisation at call time.
+    pub fn render(&self, pools: &[(&str, &Pool<Postgres>)], max_connections: u32) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests_total Requests served, by method, route pattern and status.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        let routes = self.routes.lock().unwrap();
+        for ((method, route), stats) in routes.iter() {
+            for (status, count) in &stats.by_status {
+                let _ = writeln!(out, "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}", method, escape(route), status, count);
+            }
+        }
+
+        out.push_str("# HELP http_request_duration_seconds Time spent handling requests, by method and route pattern.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((method, route), stats) in routes.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+            for (count, bound) in stats.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, count);
+            }
+            let _ = writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, stats.count);
+            let _ = writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, stats.sum_seconds);
+            let _ = writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, stats.count);
+        }
+        drop(routes);
+
+        out.push_str("# HELP auth_failures_total Rejected logins and bearer credentials, by reason.\n");
+        out.push_str("# TYPE auth_failures_total counter\n");
+        for (reason, count) in self.auth_failures.lock().unwrap().iter() {
+            let _ = writeln!(out, "auth_failures_total{{reason=\"{}\"}} {}", reason, count);
+        }
+
+        out.push_str("# HELP db_pool_connections Open connections per pool, by state.\n");
+        out.push_str("# TYPE db_pool_connections gauge\n");
+        for (name, pool) in pools {
+            let idle = pool.num_idle() as u32;
+            let size = pool.size();
+            let _ = writeln!(out, "db_pool_connections{{pool=\"{}\",state=\"idle\"}} {}", name, idle);
+            let _ = writeln!(out, "db_pool_connections{{pool=\"{}\",state=\"in_use\"}} {}", name, size.saturating_sub(idle));
+        }
+        out.push_str("# HELP db_pool_max_connections Configured connection limit of each pool.\n");
+        out.push_str("# TYPE db_pool_max_connections gauge\n");
+        for (name, _) in pools {
+            let _ = writeln!(out, "db_pool_max_connections{{pool=\"{}\"}} {}", name, max_connections);
+        }
+
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
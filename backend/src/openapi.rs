@@ -0,0 +1,157 @@
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::auth::{ApiScope, TokenPair};
+use crate::audit::AuditAction;
+use crate::correlation::MissingDays;
+use crate::errors::ErrorBody;
+use crate::import::ImportReport;
+use crate::indicators::IndicatorName;
+use crate::pagination::{AccountPage, AuditEntryPage, CompanyPage, CorporateActionPage, EoDPage, ExchangePage};
+use crate::resample::Interval;
+use crate::roles::Role;
+use crate::stats::{CompanyStats, PeriodReturns};
+use crate::services::{accounts, analytics, api_keys, audit_log, companies, corporate_actions, exchange, health, ledger, portfolio, watch_list};
+
+/// OpenAPI description of every route, served at `/api-docs/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "MarketPower API"),
+    paths(
+        health::healthz,
+        health::readyz,
+        health::metrics,
+        accounts::create_account,
+        accounts::basic_auth,
+        accounts::refresh_token,
+        accounts::logout,
+        accounts::request_password_reset,
+        accounts::confirm_password_reset,
+        accounts::change_password,
+        accounts::fetch_account_export,
+        accounts::delete_account,
+        accounts::fetch_acconts,
+        accounts::change_account_role,
+        accounts::disable_account,
+        accounts::enable_account,
+        accounts::revoke_account_sessions,
+        accounts::unlock_account,
+        api_keys::create_api_key,
+        api_keys::fetch_api_keys,
+        api_keys::revoke_api_key,
+        audit_log::fetch_audit_log,
+        analytics::correlation_matrix,
+        companies::fetch_companies,
+        companies::search_companies,
+        companies::fetch_companies_by_ticker,
+        companies::fetch_company_stats,
+        corporate_actions::fetch_corporate_actions,
+        corporate_actions::create_corporate_action,
+        corporate_actions::update_corporate_action,
+        corporate_actions::delete_corporate_action,
+        exchange::fetch_exchange,
+        ledger::fetch_ledger,
+        ledger::fetch_ledger_by_ticker,
+        ledger::import_ledger,
+        ledger::fetch_indicators,
+        portfolio::fetch_portfolio,
+        portfolio::fetch_portfolio_valuation,
+        portfolio::post_portfolio_item,
+        portfolio::delete_portfolio_item,
+        portfolio::alter_portfolio_item,
+        watch_list::fetch_watch_list,
+        watch_list::post_watchitem,
+        watch_list::delete_watch_item,
+    ),
+    components(schemas(
+        ErrorBody,
+        Role,
+        ApiScope,
+        AuditAction,
+        TokenPair,
+        CompanyPage,
+        EoDPage,
+        ExchangePage,
+        AccountPage,
+        CorporateActionPage,
+        AuditEntryPage,
+        health::Readiness,
+        accounts::CreateAccountBody,
+        accounts::ChangePasswordBody,
+        accounts::PasswordResetRequestBody,
+        accounts::PasswordResetConfirmBody,
+        accounts::ChangeRoleBody,
+        accounts::RefreshTokenBody,
+        accounts::LogoutBody,
+        accounts::AccountNoPassword,
+        accounts::AccountSummary,
+        accounts::AccountExport,
+        api_keys::CreateApiKeyBody,
+        api_keys::ApiKey,
+        api_keys::CreatedApiKey,
+        audit_log::AuditEntry,
+        analytics::CorrelationBody,
+        analytics::CorrelationMatrix,
+        MissingDays,
+        companies::Company,
+        companies::CompanyMatch,
+        CompanyStats,
+        PeriodReturns,
+        corporate_actions::CorporateActionKind,
+        corporate_actions::CorporateAction,
+        corporate_actions::CorporateActionBody,
+        exchange::Exchange,
+        ledger::EoD,
+        ledger::Order,
+        ImportReport,
+        IndicatorName,
+        ledger::IndicatorPoint,
+        ledger::IndicatorSeries,
+        Interval,
+        portfolio::PortfolioItem,
+        portfolio::PortfolioItemBody,
+        portfolio::DeletePortfolioItem,
+        portfolio::HoldingValuation,
+        portfolio::PortfolioValuation,
+        watch_list::WatchItem,
+        watch_list::WatchItemBody,
+    )),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "accounts", description = "Registration, login and account management"),
+        (name = "api_keys", description = "Personal API keys"),
+        (name = "audit_log", description = "Security and portfolio events, admins only"),
+        (name = "analytics", description = "Statistics across several tickers"),
+        (name = "companies"),
+        (name = "corporate_actions", description = "Splits and dividends, managed by moderators"),
+        (name = "exchange"),
+        (name = "ledger", description = "End of day prices"),
+        (name = "portfolio"),
+        (name = "watch_list"),
+        (name = "health", description = "Probes and metrics for operators"),
+    )
+)]
+pub struct ApiDoc;
+
+/// `basic_auth` is only accepted by `GET /auth`; everything else takes a JWT
+/// access token or a personal API key as `bearer_auth`.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("basic_auth", SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)));
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .description(Some("Access token from `GET /auth`, or a personal API key starting with `mpk_`"))
+                    .build(),
+            ),
+        );
+    }
+}
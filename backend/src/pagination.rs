@@ -0,0 +1,120 @@
+use actix_web::HttpRequest;
+use serde::Serialize;
+use sqlx::{Postgres, QueryBuilder};
+use utoipa::ToSchema;
+
+use crate::errors::ApiError;
+use crate::services::{accounts::AccountSummary, audit_log::AuditEntry, companies::Company, corporate_actions::CorporateAction, exchange::Exchange, ledger::EoD};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+/// One page of a list endpoint. `next` is the URL of the following page, or
+/// `null` on the last one.
+#[derive(Serialize, ToSchema)]
+#[aliases(CompanyPage = Page<Company>, EoDPage = Page<EoD>, ExchangePage = Page<Exchange>, AccountPage = Page<AccountSummary>, CorporateActionPage = Page<CorporateAction>)]
+pub struct Page<T> {
+    items: Vec<T>,
+    total: i64,
+    limit: i64,
+    offset: i64,
+    next: Option<String>,
+}
+
+/// One page of an append-only list, walked with a keyset cursor rather than an
+/// offset so entries added meanwhile do not shift later pages. `next` is the URL
+/// of the following page, or `null` on the last one.
+#[derive(Serialize, ToSchema)]
+#[aliases(AuditEntryPage = CursorPage<AuditEntry>)]
+pub struct CursorPage<T> {
+    items: Vec<T>,
+    total: i64,
+    limit: i64,
+    next: Option<String>,
+}
+
+/// The request's URL with `param` replaced by `replacement`.
+fn url_with(req: &HttpRequest, param: &str, replacement: &str) -> String {
+    let prefix = format!("{}=", param);
+    let mut params: Vec<&str> = req.query_string()
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with(&prefix))
+        .collect();
+    params.push(replacement);
+    format!("{}?{}", req.path(), params.join("&"))
+}
+
+/// Offset based pagination from the `limit` and `offset` query parameters.
+#[derive(Clone, Copy)]
+pub struct Pagination {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Pagination {
+    /// `limit` defaults to 100 and is clamped to 1..=1000.
+    pub fn new(limit: Option<i64>, offset: Option<i64>) -> Result<Pagination, ApiError> {
+        let offset = offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(ApiError::BadRequest("offset must not be negative".to_string()));
+        }
+        Ok(Pagination {
+            limit: limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            offset,
+        })
+    }
+
+    pub fn push_limit(&self, builder: &mut QueryBuilder<Postgres>) {
+        builder.push(" LIMIT ").push_bind(self.limit)
+            .push(" OFFSET ").push_bind(self.offset);
+    }
+
+    /// Wraps the fetched rows, linking the next page with the request's other parameters kept.
+    pub fn page<T>(&self, req: &HttpRequest, items: Vec<T>, total: i64) -> Page<T> {
+        let next_offset = self.offset + items.len() as i64;
+        let next = (!items.is_empty() && next_offset < total)
+            .then(|| url_with(req, "offset", &format!("offset={}", next_offset)));
+        Page { items, total, limit: self.limit, offset: self.offset, next }
+    }
+
+    /// Like `page`, for keyset pagination: a full page links the next one through
+    /// the `cursor` parameter, set to `cursor` of the last item.
+    pub fn cursor_page<T>(&self, req: &HttpRequest, items: Vec<T>, total: i64, cursor: impl Fn(&T) -> String) -> CursorPage<T> {
+        let next = items.last()
+            .filter(|_| items.len() as i64 == self.limit)
+            .map(|last| url_with(req, "cursor", &format!("cursor={}", cursor(last))));
+        CursorPage { items, total, limit: self.limit, next }
+    }
+}
+
+/// Validated `sort` query parameter: a column name, prefixed with `-` for descending order.
+pub struct Sort {
+    column: &'static str,
+    descending: bool,
+}
+
+impl Sort {
+    /// Only names in `allowed` are accepted, they end up verbatim in the SQL.
+    pub fn parse(value: Option<&str>, allowed: &[&'static str], default: &'static str) -> Result<Sort, ApiError> {
+        let Some(value) = value else {
+            return Ok(Sort { column: default, descending: false });
+        };
+        let (name, descending) = match value.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (value, false),
+        };
+        match allowed.iter().find(|column| **column == name) {
+            Some(column) => Ok(Sort { column, descending }),
+            None => Err(ApiError::BadRequest(format!("cannot sort by `{}`, expected one of {}", name, allowed.join(", ")))),
+        }
+    }
+
+    /// Appends `ORDER BY`; the `unique` columns break ties so pages never overlap.
+    pub fn push_order_by(&self, builder: &mut QueryBuilder<Postgres>, unique: &[&str]) {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        builder.push(format!(" ORDER BY {} {}", self.column, direction));
+        for column in unique.iter().filter(|column| **column != self.column) {
+            builder.push(format!(", {} {}", column, direction));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use argonautica::{Hasher, Verifier};
+use serde::Deserialize;
+
+use crate::auth;
+
+fn generate_salt() -> String {
+    auth::random_string(8)
+}
+
+/// Hashes the password with a freshly generated salt, returning `(hash, salt)`.
+pub fn hash_password(hash_secret: &str, password: &str) -> (String, String) {
+    let salt = generate_salt();
+    let mut hasher = Hasher::default();
+    let hash = hasher
+        .with_password(password)
+        .with_additional_data(&salt)
+        .with_secret_key(hash_secret)
+        .hash()
+        .unwrap();
+    (hash, salt)
+}
+
+pub fn verify_password(hash_secret: &str, hashed_password: &str, salt: &str, password: &str) -> bool {
+    let mut verifier = Verifier::default();
+    verifier
+        .with_hash(hashed_password)
+        .with_password(password)
+        .with_secret_key(hash_secret)
+        .with_additional_data(salt)
+        .verify()
+        .unwrap()
+}
+
+/// Minimum requirements a new password has to meet.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Returns every rule the password breaks, or `Ok` if there are none.
+    pub fn check(&self, password: &str) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+        if password.chars().count() < self.min_length {
+            violations.push(format!("must be at least {} characters long", self.min_length));
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            violations.push("must contain a lowercase letter".to_string());
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            violations.push("must contain an uppercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push("must contain a digit".to_string());
+        }
+        if self.require_symbol && password.chars().all(|c| c.is_alphanumeric()) {
+            violations.push("must contain a symbol".to_string());
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{Datelike, NaiveDate};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::services::ledger::EoD;
+
+/// Bar width of a ledger series.
+#[derive(Deserialize, ToSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    #[default]
+    Day,
+    /// Monday to Sunday.
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Interval {
+    /// First calendar day of the period containing `date`.
+    fn period_start(self, date: NaiveDate) -> NaiveDate {
+        let first_of_month = |month: u32| NaiveDate::from_ymd_opt(date.year(), month, 1).unwrap();
+        match self {
+            Interval::Day => date,
+            Interval::Week => date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64),
+            Interval::Month => first_of_month(date.month()),
+            Interval::Quarter => first_of_month((date.month0() / 3) * 3 + 1),
+            Interval::Year => first_of_month(1),
+        }
+    }
+
+    /// Merges daily bars, which must be in ascending date order, into one bar
+    /// per period: first open, highest high, lowest low, last close and
+    /// adjusted close, summed volume. Each bar is dated with the first calendar
+    /// day of its period, so the first and last bar may cover only part of it.
+    pub fn resample(self, bars: Vec<EoD>) -> Vec<EoD> {
+        if self == Interval::Day {
+            return bars;
+        }
+
+        let mut resampled: Vec<EoD> = Vec::new();
+        for bar in bars {
+            let start = self.period_start(bar.date);
+            match resampled.last_mut() {
+                Some(period) if period.date == start => {
+                    period.high = period.high.max(bar.high);
+                    period.low = period.low.min(bar.low);
+                    period.close = bar.close;
+                    period.adj_close = bar.adj_close;
+                    period.volume += bar.volume;
+                }
+                _ => resampled.push(EoD { date: start, ..bar }),
+            }
+        }
+        resampled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+    use super::*;
+    use crate::services::ledger::bar;
+
+    fn bars() -> Vec<EoD> {
+        vec![
+            bar("TEST", "2023-03-31", [10, 12, 9, 11], 100),
+            bar("TEST", "2023-04-03", [11, 15, 10, 14], 200),
+            bar("TEST", "2023-04-06", [14, 16, 8, 9], 300),
+            bar("TEST", "2023-04-10", [9, 10, 7, 8], 400),
+        ]
+    }
+
+    fn summary(bars: &[EoD]) -> Vec<(String, i64, i64, i64, i64, i64)> {
+        bars.iter()
+            .map(|bar| {
+                let whole = |value: Decimal| value.to_i64().unwrap();
+                (bar.date.to_string(), whole(bar.open), whole(bar.high), whole(bar.low), whole(bar.close), bar.volume)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn period_starts() {
+        let date: NaiveDate = "2023-08-17".parse().unwrap();
+        let start = |interval: Interval| interval.period_start(date).to_string();
+        assert_eq!(start(Interval::Day), "2023-08-17");
+        assert_eq!(start(Interval::Week), "2023-08-14");
+        assert_eq!(start(Interval::Month), "2023-08-01");
+        assert_eq!(start(Interval::Quarter), "2023-07-01");
+        assert_eq!(start(Interval::Year), "2023-01-01");
+    }
+
+    #[test]
+    fn daily_bars_are_returned_as_they_are() {
+        assert_eq!(summary(&Interval::Day.resample(bars())), summary(&bars()));
+    }
+
+    #[test]
+    fn weekly_bars_merge_monday_to_sunday() {
+        assert_eq!(summary(&Interval::Week.resample(bars())), vec![
+            ("2023-03-27".to_string(), 10, 12, 9, 11, 100),
+            ("2023-04-03".to_string(), 11, 16, 8, 9, 500),
+            ("2023-04-10".to_string(), 9, 10, 7, 8, 400),
+        ]);
+    }
+
+    #[test]
+    fn monthly_quarterly_and_yearly_bars() {
+        assert_eq!(summary(&Interval::Month.resample(bars())), vec![
+            ("2023-03-01".to_string(), 10, 12, 9, 11, 100),
+            ("2023-04-01".to_string(), 11, 16, 7, 8, 900),
+        ]);
+        assert_eq!(summary(&Interval::Quarter.resample(bars())), vec![
+            ("2023-01-01".to_string(), 10, 12, 9, 11, 100),
+            ("2023-04-01".to_string(), 11, 16, 7, 8, 900),
+        ]);
+        assert_eq!(summary(&Interval::Year.resample(bars())), vec![
+            ("2023-01-01".to_string(), 10, 16, 7, 8, 1000),
+        ]);
+        assert!(Interval::Year.resample(Vec::new()).is_empty());
+    }
+}
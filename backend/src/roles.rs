@@ -0,0 +1,98 @@
+use std::{future::{ready, Ready}, ops::Deref};
+
+use actix_web::{dev::Payload, web::Data, FromRequest, HttpMessage, HttpRequest};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use utoipa::ToSchema;
+
+use crate::{errors::ApiError, AppState, TokenClaims};
+
+/// Security level of an account. Variants are ordered by privilege so guards
+/// can compare with `>=`; the `account.security_lvl` column stores the numeric form.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn from_security_lvl(security_lvl: i32) -> Option<Role> {
+        match security_lvl {
+            0 => Some(Role::Admin),
+            1 => Some(Role::Moderator),
+            2 => Some(Role::User),
+            _ => None,
+        }
+    }
+
+    pub fn security_lvl(self) -> i32 {
+        match self {
+            Role::Admin => 0,
+            Role::Moderator => 1,
+            Role::User => 2,
+        }
+    }
+
+    pub fn pool(self, state: &AppState) -> &Pool<Postgres> {
+        match self {
+            Role::Admin => &state.db_admin,
+            Role::Moderator => &state.db_moderator,
+            Role::User => &state.db_user,
+        }
+    }
+}
+
+fn claims_with_role(req: &HttpRequest, min_role: Role) -> Result<TokenClaims, ApiError> {
+    match req.extensions().get::<TokenClaims>() {
+        Some(claims) if claims.role >= min_role => Ok(claims.clone()),
+        Some(_) => Err(ApiError::Forbidden("Insufficient privileges".to_string())),
+        None => Err(ApiError::Unauthorized("Unable to verify identity".to_string())),
+    }
+}
+
+/// Connection pool matching the role of the authenticated caller.
+pub struct Db(Pool<Postgres>);
+
+impl Deref for Db {
+    type Target = Pool<Postgres>;
+
+    fn deref(&self) -> &Pool<Postgres> {
+        &self.0
+    }
+}
+
+impl FromRequest for Db {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let state = req.app_data::<Data<AppState>>().expect("AppState must be registered");
+        ready(claims_with_role(req, Role::User).map(|claims| Db(claims.role.pool(state).clone())))
+    }
+}
+
+/// Guard admitting moderators and admins.
+pub struct Moderator;
+
+impl FromRequest for Moderator {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(claims_with_role(req, Role::Moderator).map(|_| Moderator))
+    }
+}
+
+/// Guard admitting admins only.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(claims_with_role(req, Role::Admin).map(|_| Admin))
+    }
+}
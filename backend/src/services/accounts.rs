@@ -1,28 +1,129 @@
-use actix_web::{get, post, web::{Data, Json, self, ReqData}, Responder, HttpResponse, delete};
+use actix_web::{get, post, web::{Data, Json, self, ReqData}, HttpRequest, HttpResponse, delete, patch};
 use actix_web_httpauth::extractors::basic::BasicAuth;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Serialize, Deserialize};
-use sqlx::{self, FromRow, Postgres, Pool};
-use hmac::{Hmac, Mac};
-use jwt::SignWithKey;
-use sha2::Sha256;
-use argonautica::{Hasher, Verifier};
-use rand::Rng;
+use serde_json::json;
+use sqlx::{self, FromRow, Postgres, Pool, Transaction};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{AppState, TokenClaims};
+use crate::roles::{Admin, Db, Moderator, Role};
+use crate::audit::{self, AuditAction};
+use crate::errors::ApiError;
+use crate::pagination::Pagination;
+use crate::throttle::{client_ip, Blocked};
+use crate::services::portfolio::PortfolioItem;
+use crate::services::watch_list::WatchItem;
+use crate::password::{hash_password, verify_password};
+use crate::auth::{self, hash_token, issue_token_pair, revoke_access_token, revoke_all_sessions, revoke_refresh_token};
 
-#[derive(Deserialize)]
-struct CreateAccountBody {
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CreateAccountBody {
+    /// E-mail address of the account holder; password reset tokens are mailed to it.
     login: String,
     password: String,
 }
 
-#[derive(Serialize, FromRow)]
-struct AccountNoPassword {
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct ChangePasswordBody {
+    old_password: String,
+    new_password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct PasswordResetRequestBody {
+    login: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct PasswordResetConfirmBody {
+    token: String,
+    new_password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct ChangeRoleBody {
+    role: Role,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AccountListQuery {
+    role: Option<Role>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeleteAccountQuery {
+    #[serde(default)]
+    export: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct RefreshTokenBody {
+    refresh_token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct LogoutBody {
+    refresh_token: Option<String>,
+}
+
+#[derive(FromRow)]
+struct StoredRefreshToken {
+    id: i32,
+    account_id: i32,
+    expires_at: DateTime<Utc>,
+    revoked: bool,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub(crate) struct AccountNoPassword {
     id: i32,
     login: String,
 }
 
 
+#[derive(FromRow)]
+struct AccountRow {
+    id: i32,
+    login: String,
+    security_lvl: i32,
+    disabled: bool,
+    created_at: DateTime<Utc>,
+}
+
+/// Account as exposed to admins; never carries the password hash or salt.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct AccountSummary {
+    id: i32,
+    login: String,
+    role: Option<Role>,
+    disabled: bool,
+    created_at: DateTime<Utc>,
+}
+
+impl From<AccountRow> for AccountSummary {
+    fn from(row: AccountRow) -> Self {
+        AccountSummary {
+            id: row.id,
+            login: row.login,
+            role: Role::from_security_lvl(row.security_lvl),
+            disabled: row.disabled,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct AccountExport {
+    account: AccountSummary,
+    portfolio: Vec<PortfolioItem>,
+    watch_list: Vec<WatchItem>,
+}
+
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 struct Account {
     id: i32,
@@ -30,109 +131,400 @@ struct Account {
     hashed_password: String,
     salt: String,
     security_lvl: i32,
+    disabled: bool,
 }
 
-fn generate_salt() -> String {
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
-    abcdefghijklmnopqrstuvwxyz\
-    0123456789";
-    const SALT_LEN: usize = 8;
-    let mut rng = rand::thread_rng();
+/// Stores the new password and voids every reset token of the account still outstanding.
+async fn set_password(tx: &mut Transaction<'_, Postgres>, hash_secret: &str, account_id: i32, password: &str) -> Result<(), sqlx::Error> {
+    let (hash, salt) = hash_password(hash_secret, password);
+    sqlx::query("UPDATE account SET hashed_password = $2, salt = $3 WHERE id = $1")
+        .bind(account_id)
+        .bind(hash)
+        .bind(salt)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE password_reset SET used_at = now() WHERE account_id = $1 AND used_at IS NULL")
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
 
-    let salt: String = (0..SALT_LEN)
-        .map(|_| {
-            let idx = rng.gen_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect();
+/// Logins double as the address account mails go to, so new ones must look like one.
+fn is_email(login: &str) -> bool {
+    let Some((local, domain)) = login.split_once('@') else { return false };
+    login.len() <= 254
+        && !local.is_empty()
+        && !domain.contains('@')
+        && domain.split('.').count() >= 2
+        && domain.split('.').all(|label| !label.is_empty())
+        && !login.chars().any(|c| c.is_whitespace() || c.is_control())
+}
 
-    salt
+fn blocked_error(blocked: Blocked) -> ApiError {
+    match blocked {
+        Blocked::Backoff(retry_after) => ApiError::TooManyRequests {
+            message: "Too many failed login attempts".to_string(),
+            retry_after,
+        },
+        Blocked::Locked(retry_after) => ApiError::Locked {
+            message: "Account temporarily locked".to_string(),
+            retry_after,
+        },
+    }
 }
 
+#[utoipa::path(
+    tag = "accounts",
+    security(("basic_auth" = [])),
+    responses(
+        (status = 200, description = "Access and refresh token", body = TokenPair),
+        (status = 401, description = "Incorrect login or password", body = ErrorBody),
+        (status = 403, description = "Account disabled", body = ErrorBody),
+        (status = 423, description = "Account temporarily locked", body = ErrorBody),
+        (status = 429, description = "Too many failed attempts", body = ErrorBody),
+    )
+)]
 #[get("/auth")]
-async fn basic_auth(state: Data<AppState>, credentials: BasicAuth) -> impl Responder {
-    let jwt_secret: Hmac<Sha256> = Hmac::new_from_slice(
-        std::env::var("JWT_SECRET")
-            .expect("JWT_SECRET must be set!")
-            .as_bytes()
-    ).unwrap();
+async fn basic_auth(state: Data<AppState>, req: HttpRequest, credentials: BasicAuth) -> Result<HttpResponse, ApiError> {
     let login = credentials.user_id();
-    let password = credentials.password();
-    match password {
-        None => HttpResponse::Unauthorized().json("Must provide longin and password"),
-        Some(pass) => {
-            match sqlx::query_as::<_, Account>(
-                "SELECT id, login, hashed_password, salt, security_lvl FROM account WHERE login = $1"
-            )
-            .bind(login.to_string())
-            .fetch_one(&state.db_auth)
-            .await 
-            {
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-                Ok(user) => {
-                    let hash_secret = std::env::var("HASH_SECRET").expect("HASH_SECRET must be set!");
-                    let mut verifier = Verifier::default();
-                    let is_valid = verifier
-                        .with_hash(user.hashed_password)
-                        .with_password(pass)
-                        .with_secret_key(hash_secret)
-                        .with_additional_data(user.salt)
-                        .verify()
-                        .unwrap();
-                    if is_valid {
-                        let claims = TokenClaims {id: user.id, security_lvl: user.security_lvl};
-                        let token_str = claims.sign_with_key(&jwt_secret).unwrap();
-                        HttpResponse::Ok().json(token_str)
-                    } else {
-                        HttpResponse::Unauthorized().json("incorrect login or password")
-                    }
-                }
+    let ip = client_ip(&req, &state.config.server);
+    let Some(pass) = credentials.password() else {
+        state.metrics.record_auth_failure("missing_password");
+        return Err(ApiError::Unauthorized("Must provide login and password".to_string()));
+    };
+    if let Err(blocked) = state.login_throttle.check(login, ip.as_deref()) {
+        state.metrics.record_auth_failure("throttled");
+        return Err(blocked_error(blocked));
+    }
+
+    let user = sqlx::query_as::<_, Account>(
+        "SELECT id, login, hashed_password, salt, security_lvl, disabled FROM account WHERE login = $1"
+    )
+    .bind(login.to_string())
+    .fetch_optional(&state.db_auth)
+    .await?;
+
+    match user {
+        Some(user) if verify_password(&state.config.auth.hash_secret, &user.hashed_password, &user.salt, pass) => {
+            state.login_throttle.record_success(login);
+            if user.disabled {
+                state.metrics.record_auth_failure("account_disabled");
+                return Err(ApiError::Forbidden("Account disabled".to_string()));
             }
-        },
+            audit::record(&state.db_auth, AuditAction::LoginSucceeded, Some(user.id), Some(user.id), json!({ "login": login, "ip": ip })).await;
+            let Some(role) = Role::from_security_lvl(user.security_lvl) else {
+                return Err(ApiError::Forbidden("Account has no role assigned".to_string()));
+            };
+            let tokens = issue_token_pair(&state.db_auth, &state.config.auth, user.id, role).await?;
+            Ok(HttpResponse::Ok().json(tokens))
+        }
+        user => {
+            state.login_throttle.record_failure(login, ip.as_deref());
+            state.metrics.record_auth_failure("bad_credentials");
+            audit::record(&state.db_auth, AuditAction::LoginFailed, None, user.map(|user| user.id), json!({ "login": login, "ip": ip })).await;
+            Err(ApiError::Unauthorized("incorrect login or password".to_string()))
+        }
+    }
+}
+
+/// Exchanges a refresh token for a new token pair. The presented refresh token is
+/// revoked on use; presenting an already revoked one revokes every refresh token
+/// of the account, since it means the token chain has leaked.
+#[utoipa::path(
+    tag = "accounts",
+    request_body = RefreshTokenBody,
+    responses(
+        (status = 200, description = "New access and refresh token", body = TokenPair),
+        (status = 401, description = "Invalid, expired or reused refresh token", body = ErrorBody),
+        (status = 403, description = "Account disabled", body = ErrorBody),
+    )
+)]
+#[post("/auth/refresh")]
+async fn refresh_token(state: Data<AppState>, body: Json<RefreshTokenBody>) -> Result<HttpResponse, ApiError> {
+    let token_hash = hash_token(&body.into_inner().refresh_token);
+    let mut tx = state.db_auth.begin().await?;
+
+    let stored = sqlx::query_as::<_, StoredRefreshToken>(
+        "SELECT id, account_id, expires_at, revoked FROM refresh_token WHERE token_hash = $1 FOR UPDATE"
+    )
+    .bind(token_hash)
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| ApiError::Unauthorized("Invalid refresh token".to_string()))?;
+
+    if stored.revoked {
+        sqlx::query("UPDATE refresh_token SET revoked = TRUE WHERE account_id = $1")
+            .bind(stored.account_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        return Err(ApiError::Unauthorized("Invalid refresh token".to_string()));
+    }
+    if stored.expires_at <= Utc::now() {
+        return Err(ApiError::Unauthorized("Refresh token expired".to_string()));
+    }
+
+    sqlx::query("UPDATE refresh_token SET revoked = TRUE WHERE id = $1")
+        .bind(stored.id)
+        .execute(&mut tx)
+        .await?;
+    let (security_lvl, disabled) = sqlx::query_as::<_, (i32, bool)>("SELECT security_lvl, disabled FROM account WHERE id = $1")
+        .bind(stored.account_id)
+        .fetch_one(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    if disabled {
+        return Err(ApiError::Forbidden("Account disabled".to_string()));
+    }
+    let Some(role) = Role::from_security_lvl(security_lvl) else {
+        return Err(ApiError::Forbidden("Account has no role assigned".to_string()));
+    };
+    let tokens = issue_token_pair(&state.db_auth, &state.config.auth, stored.account_id, role).await?;
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Revokes the presented access token and, when given, the refresh token of the same session.
+#[utoipa::path(
+    tag = "accounts",
+    request_body(content = Option<LogoutBody>),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Tokens revoked", body = String),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+#[post("/logout")]
+async fn logout(state: Data<AppState>, user: ReqData<TokenClaims>, body: Option<Json<LogoutBody>>) -> Result<HttpResponse, ApiError> {
+    if let Some(token) = body.and_then(|body| body.into_inner().refresh_token) {
+        revoke_refresh_token(&state.db_auth, user.id, &token).await?;
+    }
+    revoke_access_token(&state.db_auth, &user).await?;
+    Ok(HttpResponse::Ok().json("Logged out"))
+}
+
+async fn account_id_by_login(db: &Pool<Postgres>, login: &str) -> Result<i32, ApiError> {
+    sqlx::query_scalar::<_, i32>("SELECT id FROM account WHERE login = $1")
+        .bind(login)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Account not found".to_string()))
+}
+
+#[utoipa::path(
+    tag = "accounts",
+    params(("login" = String, Path, description = "Login of the account")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Sessions revoked", body = String),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Account not found", body = ErrorBody),
+    )
+)]
+#[delete("/account/{login}/sessions")]
+async fn revoke_account_sessions(_admin: Admin, db: Db, login: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let account_id = account_id_by_login(&db, &login).await?;
+    revoke_all_sessions(&db, account_id).await?;
+    Ok(HttpResponse::Ok().json("Sessions revoked"))
+}
+
+#[utoipa::path(
+    tag = "accounts",
+    params(("login" = String, Path, description = "Login of the account")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Account unlocked", body = String),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Account has no failed login attempts", body = ErrorBody),
+    )
+)]
+/// Clears the failed login counters of the account. Client addresses blocked on
+/// their own stay blocked until their lockout expires.
+#[delete("/account/{login}/lockout")]
+async fn unlock_account(_admin: Admin, state: Data<AppState>, login: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    if !state.login_throttle.unlock(&login) {
+        return Err(ApiError::NotFound("Account has no failed login attempts".to_string()));
     }
+    Ok(HttpResponse::Ok().json("Account unlocked"))
 }
 
+#[utoipa::path(
+    tag = "accounts",
+    params(AccountListQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Page of accounts, oldest first", body = AccountPage),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+    )
+)]
 #[get("/accounts")]
-async fn fetch_acconts(state: Data<AppState>) -> impl Responder {
-    match sqlx::query_as::<_, Account>("SELECT * FROM account").fetch_all(&state.db_admin).await {
-        Ok(account)=> HttpResponse::Ok().json(account),
-        Err(_) => HttpResponse::NotFound().json("No accounts found"),
+async fn fetch_acconts(_admin: Admin, db: Db, req: HttpRequest, query: web::Query<AccountListQuery>) -> Result<HttpResponse, ApiError> {
+    let pagination = Pagination::new(query.limit, query.offset)?;
+    let security_lvl = query.role.map(Role::security_lvl);
+
+    let total = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM account WHERE $1::INTEGER IS NULL OR security_lvl = $1"
+    )
+    .bind(security_lvl)
+    .fetch_one(&*db)
+    .await?;
+
+    let accounts = sqlx::query_as::<_, AccountRow>(
+        "SELECT id, login, security_lvl, disabled, created_at FROM account
+        WHERE $1::INTEGER IS NULL OR security_lvl = $1
+        ORDER BY id
+        LIMIT $2 OFFSET $3"
+    )
+    .bind(security_lvl)
+    .bind(pagination.limit)
+    .bind(pagination.offset)
+    .fetch_all(&*db)
+    .await?;
+
+    let accounts = accounts.into_iter().map(AccountSummary::from).collect();
+    Ok(HttpResponse::Ok().json(pagination.page(&req, accounts, total)))
+}
+
+/// Locks the admin accounts for the rest of the transaction, so concurrent role
+/// changes and disables are judged one after the other.
+async fn lock_admins(tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT id FROM account WHERE security_lvl = $1 FOR UPDATE")
+        .bind(Role::Admin.security_lvl())
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
+
+/// Refuses a change that left no enabled admin, since only an admin can undo it.
+async fn ensure_enabled_admin(tx: &mut Transaction<'_, Postgres>) -> Result<(), ApiError> {
+    let admins = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM account WHERE security_lvl = $1 AND NOT disabled")
+        .bind(Role::Admin.security_lvl())
+        .fetch_one(&mut *tx)
+        .await?;
+    if admins == 0 {
+        return Err(ApiError::Unprocessable("The last enabled admin cannot be demoted or disabled".to_string()));
     }
+    Ok(())
 }
 
-async fn chceck_for_login_avaliablility(login: String, database: Pool<Postgres>) -> bool {
-    let resault = sqlx::query_as::<_, Account>("SELECT * FROM account WHERE login = $1").bind(login).fetch_all(&database).await;
-    match resault {
-        Ok(x) => {
-            if x.len() != 0 {
-                false
-            } else {
-                true
-            }
-        },
-        Err(_) => false,
+/// Changing the role also revokes the account's sessions, since issued tokens carry the old role.
+#[utoipa::path(
+    tag = "accounts",
+    params(("login" = String, Path, description = "Login of the account")),
+    request_body = ChangeRoleBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Updated account", body = AccountSummary),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Account not found", body = ErrorBody),
+        (status = 422, description = "No enabled admin would be left", body = ErrorBody),
+    )
+)]
+#[patch("/account/{login}/role")]
+async fn change_account_role(_admin: Admin, state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, login: web::Path<String>, body: Json<ChangeRoleBody>) -> Result<HttpResponse, ApiError> {
+    let role = body.into_inner().role;
+    let mut tx = db.begin().await?;
+    lock_admins(&mut tx).await?;
+    let account = sqlx::query_as::<_, AccountRow>(
+        "UPDATE account SET security_lvl = $2 WHERE login = $1
+        RETURNING id, login, security_lvl, disabled, created_at"
+    )
+    .bind(login.clone())
+    .bind(role.security_lvl())
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Account not found".to_string()))?;
+    ensure_enabled_admin(&mut tx).await?;
+    tx.commit().await?;
+
+    revoke_all_sessions(&db, account.id).await?;
+    audit::record(&state.db_auth, AuditAction::RoleChanged, Some(user.id), Some(account.id), json!({ "login": account.login, "role": role })).await;
+    Ok(HttpResponse::Ok().json(AccountSummary::from(account)))
+}
+
+async fn set_account_disabled(state: &AppState, db: &Db, actor_id: i32, login: &str, disabled: bool) -> Result<HttpResponse, ApiError> {
+    let mut tx = db.begin().await?;
+    lock_admins(&mut tx).await?;
+    let account = sqlx::query_as::<_, AccountRow>(
+        "UPDATE account SET disabled = $2 WHERE login = $1
+        RETURNING id, login, security_lvl, disabled, created_at"
+    )
+    .bind(login)
+    .bind(disabled)
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Account not found".to_string()))?;
+    ensure_enabled_admin(&mut tx).await?;
+    tx.commit().await?;
+
+    // Sessions of a disabled account must not come back to life when it is re-enabled.
+    if disabled {
+        revoke_all_sessions(db, account.id).await?;
     }
+    let action = if disabled { AuditAction::AccountDisabled } else { AuditAction::AccountEnabled };
+    audit::record(&state.db_auth, action, Some(actor_id), Some(account.id), json!({ "login": account.login })).await;
+    Ok(HttpResponse::Ok().json(AccountSummary::from(account)))
 }
 
+#[utoipa::path(
+    tag = "accounts",
+    params(("login" = String, Path, description = "Login of the account")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Updated account", body = AccountSummary),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Account not found", body = ErrorBody),
+        (status = 422, description = "No enabled admin would be left", body = ErrorBody),
+    )
+)]
+#[post("/account/{login}/disable")]
+async fn disable_account(_admin: Admin, state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, login: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    set_account_disabled(&state, &db, user.id, &login, true).await
+}
+
+#[utoipa::path(
+    tag = "accounts",
+    params(("login" = String, Path, description = "Login of the account")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Updated account", body = AccountSummary),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Account not found", body = ErrorBody),
+    )
+)]
+#[post("/account/{login}/enable")]
+async fn enable_account(_admin: Admin, state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, login: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    set_account_disabled(&state, &db, user.id, &login, false).await
+}
+
+#[utoipa::path(
+    tag = "accounts",
+    request_body = CreateAccountBody,
+    responses(
+        (status = 200, description = "Account created", body = AccountNoPassword),
+        (status = 409, description = "Login already taken", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+    )
+)]
 #[post("/account")]
-async fn create_account(state: Data<AppState>, body: Json<CreateAccountBody>) -> impl Responder {
+async fn create_account(state: Data<AppState>, body: Json<CreateAccountBody>) -> Result<HttpResponse, ApiError> {
     let account: CreateAccountBody = body.into_inner();
-    let hash_secret = std::env::var("HASH_SECRET").expect("HASH_SECRET must be set!");
-    let mut hasher = Hasher::default();
-    let salt = generate_salt();
-
-    if !chceck_for_login_avaliablility(account.login.clone(), state.db_admin.clone()).await { 
-        return HttpResponse::InternalServerError().json("login not avaliable");
+    if !is_email(&account.login) {
+        return Err(ApiError::Validation(vec!["login must be an e-mail address".to_string()]));
     }
+    state.config.password_policy.check(&account.password).map_err(ApiError::Validation)?;
 
-    let hash = hasher
-        .with_password(account.password)
-        .with_additional_data(&salt)
-        .with_secret_key(hash_secret)
-        .hash()
-        .unwrap();
+    let (hash, salt) = hash_password(&state.config.auth.hash_secret, &account.password);
 
-    match sqlx::query_as::<_, AccountNoPassword>(
+    // A taken login surfaces as a unique violation on `account.login`, answered with 409.
+    let user = sqlx::query_as::<_, AccountNoPassword>(
         "Insert INTO account (login, hashed_password, salt, security_lvl)
         VALUES ($1, $2, $3, $4)
         RETURNING id, login"
@@ -140,35 +532,234 @@ async fn create_account(state: Data<AppState>, body: Json<CreateAccountBody>) ->
     .bind(account.login)
     .bind(hash)
     .bind(salt)
-    .bind(2)
+    .bind(Role::User.security_lvl())
     .fetch_one(&state.db_admin)
-    .await
-    {
-        Ok(user) => HttpResponse::Ok().json(user),
-        Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error))
+    .await?;
+
+    audit::record(&state.db_auth, AuditAction::AccountCreated, Some(user.id), Some(user.id), json!({ "login": user.login })).await;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+#[utoipa::path(
+    tag = "accounts",
+    request_body = ChangePasswordBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Password changed, new access and refresh token", body = TokenPair),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 423, description = "Account temporarily locked", body = ErrorBody),
+        (status = 429, description = "Too many failed attempts", body = ErrorBody),
+    )
+)]
+/// Changes the password and signs out every session of the account, including
+/// the calling one, which continues with the returned token pair. Wrong old
+/// passwords count as failed logins of the account.
+#[patch("/account/password")]
+async fn change_password(state: Data<AppState>, req: HttpRequest, user: ReqData<TokenClaims>, body: Json<ChangePasswordBody>) -> Result<HttpResponse, ApiError> {
+    let body: ChangePasswordBody = body.into_inner();
+    let account = sqlx::query_as::<_, Account>(
+        "SELECT id, login, hashed_password, salt, security_lvl, disabled FROM account WHERE id = $1"
+    )
+    .bind(user.id)
+    .fetch_one(&state.db_auth)
+    .await?;
+
+    let ip = client_ip(&req, &state.config.server);
+    state.login_throttle.check(&account.login, ip.as_deref()).map_err(blocked_error)?;
+    if !verify_password(&state.config.auth.hash_secret, &account.hashed_password, &account.salt, &body.old_password) {
+        state.login_throttle.record_failure(&account.login, ip.as_deref());
+        return Err(ApiError::Unauthorized("incorrect password".to_string()));
     }
+    state.login_throttle.record_success(&account.login);
+    state.config.password_policy.check(&body.new_password).map_err(ApiError::Validation)?;
+
+    let mut tx = state.db_admin.begin().await?;
+    set_password(&mut tx, &state.config.auth.hash_secret, account.id, &body.new_password).await?;
+    tx.commit().await?;
+    revoke_all_sessions(&state.db_admin, account.id).await?;
+    let Some(role) = Role::from_security_lvl(account.security_lvl) else {
+        return Err(ApiError::Forbidden("Account has no role assigned".to_string()));
+    };
+    let tokens = issue_token_pair(&state.db_auth, &state.config.auth, account.id, role).await?;
+    Ok(HttpResponse::Ok().json(tokens))
 }
 
-#[delete("/account/{login}")]
-async fn delete_account(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>,  login: web::Path<String>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, Account>("DELETE FROM account WHERE login = $1 ")
-            .bind(login.clone())
-            .fetch_one(db)
-            .await
-            {
-                Ok(account) => HttpResponse::Ok().json(account),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
+/// Mails a single-use reset token to the account. Responds the same way whether
+/// or not the login exists so the endpoint cannot be used to probe for accounts;
+/// mail delivery failures are only logged for the same reason. Requests are
+/// throttled per login and per client like failed logins.
+#[utoipa::path(
+    tag = "accounts",
+    request_body = PasswordResetRequestBody,
+    responses(
+        (status = 200, description = "Reset token sent if the account exists", body = String),
+        (status = 429, description = "Too many reset requests", body = ErrorBody),
+    )
+)]
+#[post("/account/password/reset")]
+async fn request_password_reset(state: Data<AppState>, req: HttpRequest, body: Json<PasswordResetRequestBody>) -> Result<HttpResponse, ApiError> {
+    let login = body.into_inner().login;
+    let ip = client_ip(&req, &state.config.server);
+    if let Err(Blocked::Backoff(retry_after) | Blocked::Locked(retry_after)) = state.login_throttle.check_reset_request(&login, ip.as_deref()) {
+        return Err(ApiError::TooManyRequests {
+            message: "Too many password reset requests".to_string(),
+            retry_after,
+        });
+    }
+    state.login_throttle.record_reset_request(&login, ip.as_deref());
+
+    let account_id = sqlx::query_scalar::<_, i32>("SELECT id FROM account WHERE login = $1")
+        .bind(&login)
+        .fetch_optional(&state.db_auth)
+        .await?;
+
+    if let Some(account_id) = account_id {
+        let token = auth::random_string(48);
+        let lifetime = Duration::minutes(state.config.auth.password_reset_minutes);
+        sqlx::query(
+            "INSERT INTO password_reset (account_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)"
+        )
+        .bind(account_id)
+        .bind(hash_token(&token))
+        .bind(Utc::now() + lifetime)
+        .execute(&state.db_auth)
+        .await?;
+
+        let body = format!(
+            "Use the following token to reset your MarketPower password:\n\n{}\n\nThe token expires in {} minutes and can be used once.",
+            token,
+            lifetime.num_minutes()
+        );
+        if let Err(error) = state.mailer.send(&login, "MarketPower password reset", &body) {
+            log::error!("cannot send password reset mail to {}: {}", login, error);
         }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
     }
+
+    Ok(HttpResponse::Ok().json("If the account exists a reset token has been sent"))
 }
 
+#[utoipa::path(
+    tag = "accounts",
+    request_body = PasswordResetConfirmBody,
+    responses(
+        (status = 200, description = "Password reset", body = String),
+        (status = 401, description = "Invalid or expired reset token", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+    )
+)]
+#[post("/account/password/reset/confirm")]
+async fn confirm_password_reset(state: Data<AppState>, body: Json<PasswordResetConfirmBody>) -> Result<HttpResponse, ApiError> {
+    let body: PasswordResetConfirmBody = body.into_inner();
+    state.config.password_policy.check(&body.new_password).map_err(ApiError::Validation)?;
+
+    // The token is only spent once the new password is stored.
+    let mut tx = state.db_admin.begin().await?;
+    let account_id = sqlx::query_scalar::<_, i32>(
+        "UPDATE password_reset SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING account_id"
+    )
+    .bind(hash_token(&body.token))
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| ApiError::Unauthorized("Invalid or expired reset token".to_string()))?;
+
+    set_password(&mut tx, &state.config.auth.hash_secret, account_id, &body.new_password).await?;
+    tx.commit().await?;
+    revoke_all_sessions(&state.db_admin, account_id).await?;
+    Ok(HttpResponse::Ok().json("Password reset"))
+}
+
+async fn export_account(db: &Pool<Postgres>, account: AccountSummary) -> Result<AccountExport, sqlx::Error> {
+    let portfolio = sqlx::query_as::<_, PortfolioItem>("SELECT * FROM portfolio WHERE account_id = $1")
+        .bind(account.id)
+        .fetch_all(db)
+        .await?;
+    let watch_list = sqlx::query_as::<_, WatchItem>("SELECT * FROM watch_list WHERE account_id = $1")
+        .bind(account.id)
+        .fetch_all(db)
+        .await?;
+    Ok(AccountExport { account, portfolio, watch_list })
+}
+
+/// Everything stored about the caller's account, for download before deleting it.
+#[utoipa::path(
+    tag = "accounts",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Account data", body = AccountExport),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+#[get("/account/export")]
+async fn fetch_account_export(state: Data<AppState>, user: ReqData<TokenClaims>) -> Result<HttpResponse, ApiError> {
+    let account = sqlx::query_as::<_, AccountRow>(
+        "SELECT id, login, security_lvl, disabled, created_at FROM account WHERE id = $1"
+    )
+    .bind(user.id)
+    .fetch_one(&state.db_admin)
+    .await?;
+    let export = export_account(&state.db_admin, AccountSummary::from(account)).await?;
+    Ok(HttpResponse::Ok().json(export))
+}
+
+/// Anyone may delete their own account. Moderators may also delete plain user
+/// accounts and admins any account. The account's portfolio, watch list and
+/// credentials are removed with it; `?export=true` returns them in the response.
+#[utoipa::path(
+    tag = "accounts",
+    params(("login" = String, Path, description = "Login of the account"), DeleteAccountQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Account deleted, with its data when `export` is set", body = AccountExport),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Account not found", body = ErrorBody),
+    )
+)]
+#[delete("/account/{login}")]
+async fn delete_account(privileged: Option<Moderator>, state: Data<AppState>, user: ReqData<TokenClaims>, login: web::Path<String>, query: web::Query<DeleteAccountQuery>) -> Result<HttpResponse, ApiError> {
+    let account = sqlx::query_as::<_, AccountRow>(
+        "SELECT id, login, security_lvl, disabled, created_at FROM account WHERE login = $1"
+    )
+    .bind(login.as_str())
+    .fetch_optional(&state.db_admin)
+    .await?
+    .map(AccountSummary::from)
+    .ok_or_else(|| ApiError::NotFound("Account not found".to_string()))?;
+
+    let allowed = account.id == user.id
+        || user.role == Role::Admin
+        || (privileged.is_some() && !matches!(account.role, Some(role) if role >= user.role));
+    if !allowed {
+        return Err(ApiError::Forbidden("Insufficient privileges".to_string()));
+    }
+
+    let account_id = account.id;
+    let export = if query.export {
+        Some(export_account(&state.db_admin, account).await?)
+    } else {
+        None
+    };
+
+    let mut tx = state.db_admin.begin().await?;
+    for table in ["portfolio", "watch_list", "refresh_token", "revoked_token", "password_reset", "api_key"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE account_id = $1", table))
+            .bind(account_id)
+            .execute(&mut tx)
+            .await?;
+    }
+    sqlx::query("DELETE FROM account WHERE id = $1")
+        .bind(account_id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    audit::record(&state.db_auth, AuditAction::AccountDeleted, Some(user.id), Some(account_id), json!({ "login": login.as_str() })).await;
+    match export {
+        Some(export) => Ok(HttpResponse::Ok().json(export)),
+        None => Ok(HttpResponse::Ok().json("Account deleted")),
+    }
+}
@@ -0,0 +1,134 @@
+use actix_web::{post, web::Json, HttpResponse};
+use serde::{Serialize, Deserialize};
+use chrono::NaiveDate;
+use rust_decimal::prelude::ToPrimitive;
+use utoipa::ToSchema;
+
+use crate::roles::Db;
+use crate::errors::ApiError;
+use crate::adjust::adjust;
+use crate::correlation::{self, MissingDays};
+use super::corporate_actions;
+use super::ledger::EoD;
+
+const MAX_TICKERS: usize = 50;
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CorrelationBody {
+    /// Between 2 and 50 distinct tickers.
+    tickers: Vec<String>,
+    /// First trading day to include, `YYYY-MM-DD`.
+    from: Option<NaiveDate>,
+    /// Last trading day to include, `YYYY-MM-DD`.
+    to: Option<NaiveDate>,
+    #[serde(default)]
+    missing: MissingDays,
+    /// Adjust prices for splits and dividends first.
+    #[serde(default)]
+    adjusted: bool,
+}
+
+/// Matrices of daily simple returns, rows and columns in the order of `tickers`.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CorrelationMatrix {
+    tickers: Vec<String>,
+    /// First and last aligned trading day.
+    from: NaiveDate,
+    to: NaiveDate,
+    /// Number of daily returns each entry is computed from.
+    observations: usize,
+    /// `null` where a ticker's price never changed.
+    correlation: Vec<Vec<Option<f64>>>,
+    /// Sample covariance, not annualised.
+    covariance: Vec<Vec<f64>>,
+}
+
+impl CorrelationBody {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut problems = Vec::new();
+        if self.tickers.len() < 2 || self.tickers.len() > MAX_TICKERS {
+            problems.push(format!("tickers must list between 2 and {} tickers", MAX_TICKERS));
+        }
+        for (index, ticker) in self.tickers.iter().enumerate() {
+            if self.tickers[..index].contains(ticker) {
+                problems.push(format!("ticker `{}` is listed more than once", ticker));
+            }
+        }
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                problems.push("from must not be after to".to_string());
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(problems))
+        }
+    }
+}
+
+#[utoipa::path(
+    tag = "analytics",
+    request_body = CorrelationBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Correlation and covariance of daily returns", body = CorrelationMatrix),
+        (status = 400, description = "Malformed body", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 422, description = "Invalid tickers or window, or too few common trading days", body = ErrorBody),
+    )
+)]
+/// Aligns the close series of the tickers on common trading days and correlates
+/// their daily returns. The closes are raw unless `adjusted` is set.
+#[post("/analytics/correlation")]
+async fn correlation_matrix(db: Db, body: Json<CorrelationBody>) -> Result<HttpResponse, ApiError> {
+    body.validate()?;
+
+    let mut prices = sqlx::query_as::<_, EoD>(
+        "SELECT * FROM ledger
+        WHERE ticker = ANY($1)
+        AND ($2::DATE IS NULL OR date >= $2)
+        AND ($3::DATE IS NULL OR date <= $3)
+        ORDER BY ticker, date"
+    )
+    .bind(&body.tickers)
+    .bind(body.from)
+    .bind(body.to)
+    .fetch_all(&*db)
+    .await?;
+    if body.adjusted {
+        adjust(&mut prices, &corporate_actions::adjustments(&db, &body.tickers).await?);
+    }
+
+    let series: Vec<Vec<(NaiveDate, f64)>> = body.tickers.iter()
+        .map(|ticker| {
+            prices.iter()
+                .filter(|bar| &bar.ticker == ticker)
+                .filter_map(|bar| Some((bar.date, bar.close.to_f64()?)))
+                .collect()
+        })
+        .collect();
+    let without_prices: Vec<String> = body.tickers.iter().zip(&series)
+        .filter(|(_, series)| series.is_empty())
+        .map(|(ticker, _)| format!("ticker `{}` is unknown or has no prices in the window", ticker))
+        .collect();
+    if !without_prices.is_empty() {
+        return Err(ApiError::Validation(without_prices));
+    }
+
+    let aligned = correlation::align(&series, body.missing);
+    if aligned.dates.len() < 3 {
+        return Err(ApiError::Unprocessable("Fewer than 3 common trading days in the window".to_string()));
+    }
+    let returns: Vec<Vec<f64>> = aligned.prices.iter().map(|prices| correlation::returns(prices)).collect();
+    let covariance = correlation::covariance(&returns);
+
+    Ok(HttpResponse::Ok().json(CorrelationMatrix {
+        tickers: body.tickers.clone(),
+        from: aligned.dates[0],
+        to: aligned.dates[aligned.dates.len() - 1],
+        observations: aligned.dates.len() - 1,
+        correlation: correlation::correlation(&covariance),
+        covariance,
+    }))
+}
@@ -0,0 +1,135 @@
+use actix_web::{get, post, web::{Data, Json, self, ReqData}, HttpResponse, delete};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+use sqlx::{self, FromRow};
+use utoipa::ToSchema;
+
+use crate::{AppState, TokenClaims};
+use crate::auth::{self, hash_token, ApiScope, API_KEY_PREFIX};
+use crate::errors::ApiError;
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_EXPIRES_IN_DAYS: i64 = 3650;
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CreateApiKeyBody {
+    /// Between 1 and 100 characters.
+    name: String,
+    scopes: Vec<ApiScope>,
+    /// Between 1 and 3650; keys without it do not expire.
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub(crate) struct ApiKey {
+    id: i32,
+    name: String,
+    prefix: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Returned once on creation; only the hash of `key` is stored.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CreatedApiKey {
+    key: String,
+    #[serde(flatten)]
+    api_key: ApiKey,
+}
+
+#[utoipa::path(
+    tag = "api_keys",
+    request_body = CreateApiKeyBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Created key, the only time it is returned in full", body = CreatedApiKey),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+    )
+)]
+#[post("/api_keys")]
+async fn create_api_key(state: Data<AppState>, user: ReqData<TokenClaims>, body: Json<CreateApiKeyBody>) -> Result<HttpResponse, ApiError> {
+    let body: CreateApiKeyBody = body.into_inner();
+    let name = body.name.trim();
+    let mut problems = Vec::new();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        problems.push(format!("name must be between 1 and {} characters", MAX_NAME_LENGTH));
+    }
+    if body.expires_in_days.is_some_and(|days| !(1..=MAX_EXPIRES_IN_DAYS).contains(&days)) {
+        problems.push(format!("expires_in_days must be between 1 and {}", MAX_EXPIRES_IN_DAYS));
+    }
+    if body.scopes.is_empty() {
+        problems.push("at least one scope is required".to_string());
+    }
+    if !problems.is_empty() {
+        return Err(ApiError::Validation(problems));
+    }
+
+    let key = format!("{}{}", API_KEY_PREFIX, auth::random_string(40));
+    let prefix: String = key.chars().take(API_KEY_PREFIX.len() + 6).collect();
+    let mut scopes: Vec<String> = body.scopes.iter().map(|scope| scope.as_str().to_string()).collect();
+    scopes.sort();
+    scopes.dedup();
+    let expires_at = body.expires_in_days.map(|days| Utc::now() + Duration::days(days));
+
+    let api_key = sqlx::query_as::<_, ApiKey>(
+        "INSERT INTO api_key (account_id, name, prefix, key_hash, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, name, prefix, scopes, created_at, last_used_at, expires_at"
+    )
+    .bind(user.id)
+    .bind(name)
+    .bind(prefix)
+    .bind(hash_token(&key))
+    .bind(scopes)
+    .bind(expires_at)
+    .fetch_one(&state.db_auth)
+    .await?;
+    Ok(HttpResponse::Ok().json(CreatedApiKey { key, api_key }))
+}
+
+#[utoipa::path(
+    tag = "api_keys",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Active keys of the caller", body = [ApiKey]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+#[get("/api_keys")]
+async fn fetch_api_keys(state: Data<AppState>, user: ReqData<TokenClaims>) -> Result<HttpResponse, ApiError> {
+    let api_keys = sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, prefix, scopes, created_at, last_used_at, expires_at
+        FROM api_key WHERE account_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at"
+    )
+    .bind(user.id)
+    .fetch_all(&state.db_auth)
+    .await?;
+    Ok(HttpResponse::Ok().json(api_keys))
+}
+
+#[utoipa::path(
+    tag = "api_keys",
+    params(("id" = i32, Path, description = "Id of the API key")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "API key revoked", body = String),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 404, description = "API key not found", body = ErrorBody),
+    )
+)]
+#[delete("/api_keys/{id}")]
+async fn revoke_api_key(state: Data<AppState>, user: ReqData<TokenClaims>, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let result = sqlx::query("UPDATE api_key SET revoked_at = now() WHERE id = $1 AND account_id = $2 AND revoked_at IS NULL")
+    .bind(id.into_inner())
+    .bind(user.id)
+    .execute(&state.db_auth)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("API key not found".to_string()));
+    }
+    Ok(HttpResponse::Ok().json("API key revoked"))
+}
@@ -0,0 +1,98 @@
+use actix_web::{get, web::Query, HttpRequest, HttpResponse};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use sqlx::{self, FromRow, Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::audit::AuditAction;
+use crate::roles::{Admin, Db};
+use crate::errors::ApiError;
+use crate::pagination::Pagination;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuditLogQuery {
+    account_id: Option<i32>,
+    action: Option<AuditAction>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    /// Defaults to 100, at most 1000.
+    limit: Option<i64>,
+    /// Position after which the page starts, taken from the `next` link of the previous page.
+    cursor: Option<String>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub(crate) struct AuditEntry {
+    id: i64,
+    occurred_at: DateTime<Utc>,
+    action: String,
+    actor_id: Option<i32>,
+    subject_id: Option<i32>,
+    #[schema(value_type = Object)]
+    details: Value,
+}
+
+impl AuditEntry {
+    fn cursor(&self) -> String {
+        format!("{}_{}", self.occurred_at.to_rfc3339_opts(SecondsFormat::Micros, true), self.id)
+    }
+}
+
+fn parse_cursor(cursor: &str) -> Result<(DateTime<Utc>, i64), ApiError> {
+    cursor.split_once('_')
+        .and_then(|(occurred_at, id)| Some((occurred_at.parse().ok()?, id.parse().ok()?)))
+        .ok_or_else(|| ApiError::BadRequest(format!("invalid cursor `{}`", cursor)))
+}
+
+/// Newest entries first. `account_id` matches entries the account either performed or was affected by.
+#[utoipa::path(
+    tag = "audit_log",
+    params(AuditLogQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Page of matching entries", body = AuditEntryPage),
+        (status = 400, description = "Invalid cursor", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+    )
+)]
+#[get("/audit")]
+async fn fetch_audit_log(_admin: Admin, db: Db, req: HttpRequest, query: Query<AuditLogQuery>) -> Result<HttpResponse, ApiError> {
+    let query: AuditLogQuery = query.into_inner();
+    let pagination = Pagination::new(query.limit, None)?;
+    let cursor = query.cursor.as_deref().map(parse_cursor).transpose()?;
+
+    let filter = |builder: &mut QueryBuilder<Postgres>| {
+        if let Some(account_id) = query.account_id {
+            builder.push(" AND (actor_id = ").push_bind(account_id)
+                .push(" OR subject_id = ").push_bind(account_id).push(")");
+        }
+        if let Some(action) = query.action {
+            builder.push(" AND action = ").push_bind(action.as_str());
+        }
+        if let Some(from) = query.from {
+            builder.push(" AND occurred_at >= ").push_bind(from);
+        }
+        if let Some(to) = query.to {
+            builder.push(" AND occurred_at < ").push_bind(to);
+        }
+    };
+
+    let mut count: QueryBuilder<Postgres> = QueryBuilder::new("SELECT COUNT(*) FROM audit_log WHERE TRUE");
+    filter(&mut count);
+    let (total,) = count.build_query_as::<(i64,)>().fetch_one(&*db).await?;
+
+    let mut select: QueryBuilder<Postgres> = QueryBuilder::new(
+        "SELECT id, occurred_at, action, actor_id, subject_id, details FROM audit_log WHERE TRUE"
+    );
+    filter(&mut select);
+    if let Some((occurred_at, id)) = cursor {
+        select.push(" AND (occurred_at, id) < (").push_bind(occurred_at).push(", ").push_bind(id).push(")");
+    }
+    select.push(" ORDER BY occurred_at DESC, id DESC LIMIT ").push_bind(pagination.limit);
+
+    let entries = select.build_query_as::<AuditEntry>().fetch_all(&*db).await?;
+    Ok(HttpResponse::Ok().json(pagination.cursor_page(&req, entries, total, AuditEntry::cursor)))
+}
@@ -1,11 +1,19 @@
-use actix_web::{get, web::{Data, ReqData, self}, Responder, HttpResponse};
+use actix_web::{get, web::{self, Query}, HttpRequest, HttpResponse};
 use serde::{Serialize, Deserialize};
-use sqlx::{self, FromRow};
+use sqlx::{self, FromRow, Pool, Postgres, QueryBuilder};
+use chrono::NaiveDate;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::{AppState, TokenClaims};
+use crate::roles::Db;
+use crate::errors::ApiError;
+use crate::pagination::{Pagination, Sort};
+use crate::adjust::adjust;
+use crate::stats;
+use super::corporate_actions;
+use super::ledger::EoD;
 
-#[derive(Serialize, Deserialize, Debug, FromRow)]
-struct Company {
+#[derive(Serialize, Deserialize, Debug, FromRow, ToSchema)]
+pub(crate) struct Company {
     ticker: String,
     name: String,
     sector: String,
@@ -13,58 +21,236 @@ struct Company {
     mic: String,
 }
 
-#[get("/companies")]
-async fn fetch_companies(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>) -> impl Responder{
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, Company>("SELECT * FROM company")
-            .fetch_all(db)
-            .await
-            {
-                Ok(companies) => HttpResponse::Ok().json(companies),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+const SORT_COLUMNS: [&str; 5] = ["ticker", "name", "sector", "industry", "mic"];
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct CompanyQuery {
+    sector: Option<String>,
+    industry: Option<String>,
+    /// Market identifier code of the listing exchange.
+    mic: Option<String>,
+    /// `ticker`, `name`, `sector`, `industry` or `mic`, prefixed with `-` for descending order.
+    sort: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+fn push_filters(builder: &mut QueryBuilder<Postgres>, query: &CompanyQuery) {
+    if let Some(sector) = &query.sector {
+        builder.push(" AND sector = ").push_bind(sector.clone());
     }
+    if let Some(industry) = &query.industry {
+        builder.push(" AND industry = ").push_bind(industry.clone());
+    }
+    if let Some(mic) = &query.mic {
+        builder.push(" AND mic = ").push_bind(mic.clone());
+    }
+}
+
+#[utoipa::path(
+    tag = "companies",
+    params(CompanyQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Page of companies", body = CompanyPage),
+        (status = 400, description = "Invalid sort column or offset", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+#[get("/companies")]
+async fn fetch_companies(db: Db, req: HttpRequest, query: Query<CompanyQuery>) -> Result<HttpResponse, ApiError> {
+    let query: CompanyQuery = query.into_inner();
+    let pagination = Pagination::new(query.limit, query.offset)?;
+    let sort = Sort::parse(query.sort.as_deref(), &SORT_COLUMNS, "ticker")?;
+
+    let mut count: QueryBuilder<Postgres> = QueryBuilder::new("SELECT COUNT(*) FROM company WHERE TRUE");
+    push_filters(&mut count, &query);
+    let (total,) = count.build_query_as::<(i64,)>().fetch_one(&*db).await?;
+
+    let mut select: QueryBuilder<Postgres> = QueryBuilder::new("SELECT * FROM company WHERE TRUE");
+    push_filters(&mut select, &query);
+    sort.push_order_by(&mut select, &["ticker"]);
+    pagination.push_limit(&mut select);
+    let companies = select.build_query_as::<Company>().fetch_all(&*db).await?;
+
+    Ok(HttpResponse::Ok().json(pagination.page(&req, companies, total)))
+}
+
+const MAX_QUERY_LENGTH: usize = 100;
+
+/// Minimum `word_similarity` for a fuzzy name match. pg_trgm's default of 0.6
+/// misses simple typos such as "Mircosoft".
+const WORD_SIMILARITY_THRESHOLD: &str = "0.3";
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct SearchQuery {
+    /// Part of a ticker or company name, typos allowed.
+    q: String,
+    /// Number of matches, 10 by default and at most 50.
+    limit: Option<i64>,
 }
 
-#[get("/companies_test")]
-async fn fetch_comp_test(state: Data<AppState>,) -> impl Responder{
-    match sqlx::query_as::<_, Company>("SELECT * FROM company LIMIT 5")
-    .fetch_all(&state.db_admin)
-    .await
-    {
-        Ok(companies) => HttpResponse::Ok().json(companies),
-        Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
+/// Search hit for autocompletion; fetch `/companies/{ticker}` for the details.
+#[derive(Serialize, FromRow, ToSchema)]
+pub(crate) struct CompanyMatch {
+    ticker: String,
+    name: String,
+    mic: String,
+    /// Higher is better: exact ticker matches first, then ticker and name
+    /// prefixes, then by trigram similarity.
+    score: f32,
+}
+
+#[utoipa::path(
+    tag = "companies",
+    params(SearchQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Best matches first", body = [CompanyMatch]),
+        (status = 400, description = "Empty or overlong query", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+/// Prefix and fuzzy search over tickers and company names, backed by the
+/// trigram indexes from migration 0006.
+#[get("/companies/search")]
+async fn search_companies(db: Db, query: Query<SearchQuery>) -> Result<HttpResponse, ApiError> {
+    let q = query.q.trim();
+    if q.is_empty() || q.chars().count() > MAX_QUERY_LENGTH {
+        return Err(ApiError::BadRequest(format!("q must be between 1 and {} characters", MAX_QUERY_LENGTH)));
     }
+    let prefix = format!("{}%", q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+
+    // `%` and `<%` compare against pg_trgm's similarity thresholds; the word
+    // threshold is lowered for this transaction only.
+    let mut tx = db.begin().await?;
+    sqlx::query("SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)")
+    .bind(WORD_SIMILARITY_THRESHOLD)
+    .execute(&mut tx)
+    .await?;
+    let matches = sqlx::query_as::<_, CompanyMatch>(
+        "SELECT ticker, name, mic,
+            (CASE
+                WHEN upper(ticker) = upper($1) THEN 3
+                WHEN ticker ILIKE $2 THEN 2
+                WHEN name ILIKE $2 OR name ILIKE ('% ' || $2) THEN 1
+                ELSE 0
+            END + GREATEST(similarity(ticker, $1), word_similarity($1, name)))::FLOAT4 AS score
+        FROM company
+        WHERE ticker ILIKE $2
+            OR name ILIKE $2
+            OR name ILIKE ('% ' || $2)
+            OR ticker % $1
+            OR $1 <% name
+        ORDER BY score DESC, ticker
+        LIMIT $3"
+    )
+    .bind(q)
+    .bind(prefix)
+    .bind(query.limit.unwrap_or(10).clamp(1, 50))
+    .fetch_all(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(HttpResponse::Ok().json(matches))
 }
 
+#[utoipa::path(
+    tag = "companies",
+    params(("ticker" = String, Path, description = "Ticker symbol")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Company with the ticker, as a one-element array", body = [Company]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 404, description = "Company not found", body = ErrorBody),
+    )
+)]
 #[get("/companies/{ticker}")]
-async fn fetch_companies_by_ticker(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>, ticker: web::Path<String>) -> impl Responder{
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, Company>("SELECT * FROM company WHERE ticker = $1") 
-            .bind(ticker.clone())
-            .fetch_all(db)
-            .await
-            {
-                Ok(companies) => HttpResponse::Ok().json(companies),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
+async fn fetch_companies_by_ticker(db: Db, ticker: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let companies = sqlx::query_as::<_, Company>("SELECT * FROM company WHERE ticker = $1")
+    .bind(ticker.clone())
+    .fetch_all(&*db)
+    .await?;
+    if companies.is_empty() {
+        return Err(ApiError::NotFound("Company not found".to_string()));
+    }
+    Ok(HttpResponse::Ok().json(companies))
+}
+
+/// Tells an unknown ticker apart from a ticker without matching prices.
+pub(crate) async fn ensure_company(db: &Pool<Postgres>, ticker: &str) -> Result<(), ApiError> {
+    sqlx::query("SELECT 1 FROM company WHERE ticker = $1")
+    .bind(ticker)
+    .fetch_optional(db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Company not found".to_string()))?;
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct StatsQuery {
+    /// Compute the statistics as of this day, `YYYY-MM-DD`; defaults to the latest prices.
+    as_of: Option<NaiveDate>,
+    /// Adjust for splits and dividends with an ex-date up to `as_of`, the default.
+    /// `false` computes price returns on the raw closes, which jump at every split;
+    /// `adj_close` then reports the raw close too.
+    #[serde(default = "adjusted_by_default")]
+    adjusted: bool,
+}
+
+fn adjusted_by_default() -> bool {
+    true
+}
+
+#[utoipa::path(
+    tag = "companies",
+    params(("ticker" = String, Path, description = "Ticker symbol"), StatsQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Returns, volatility, drawdown and 52 week range", body = CompanyStats),
+        (status = 400, description = "Malformed date", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 404, description = "Unknown ticker or no prices on or before `as_of`", body = ErrorBody),
+    )
+)]
+/// Statistics derived from the ledger as of the last trading day on or before
+/// `as_of`. Later prices and corporate actions are ignored, so a snapshot for a
+/// past date stays the same as new data arrives. The stored `adj_close` is not
+/// used, since later imports overwrite it whenever the vendor re-adjusts.
+#[get("/companies/{ticker}/stats")]
+async fn fetch_company_stats(db: Db, ticker: web::Path<String>, query: Query<StatsQuery>) -> Result<HttpResponse, ApiError> {
+    let as_of = sqlx::query_scalar::<_, Option<NaiveDate>>(
+        "SELECT MAX(date) FROM ledger WHERE ticker = $1 AND ($2::DATE IS NULL OR date <= $2)"
+    )
+    .bind(ticker.clone())
+    .bind(query.as_of)
+    .fetch_one(&*db)
+    .await?;
+    let Some(as_of) = as_of else {
+        ensure_company(&db, &ticker).await?;
+        return Err(ApiError::NotFound("No prices on or before the requested date".to_string()));
+    };
+
+    let mut prices = sqlx::query_as::<_, EoD>(
+        "SELECT * FROM ledger WHERE ticker = $1 AND date BETWEEN $2 AND $3 ORDER BY date ASC"
+    )
+    .bind(ticker.clone())
+    .bind(stats::history_start(as_of))
+    .bind(as_of)
+    .fetch_all(&*db)
+    .await?;
+    if query.adjusted {
+        let mut adjustments = corporate_actions::adjustments(&db, std::slice::from_ref(&*ticker)).await?;
+        adjustments.retain(|adjustment| adjustment.ex_date() <= as_of);
+        adjust(&mut prices, &adjustments);
+    } else {
+        for bar in &mut prices {
+            bar.adj_close = bar.close;
         }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
     }
+
+    let stats = stats::compute(&prices).ok_or_else(|| ApiError::Internal("stats computed without prices".to_string()))?;
+    Ok(HttpResponse::Ok().json(stats))
 }
@@ -0,0 +1,234 @@
+use actix_web::{delete, get, post, put, web::{self, Json, Query}, HttpRequest, HttpResponse};
+use serde::{Serialize, Deserialize};
+use sqlx::{self, FromRow, Pool, Postgres, QueryBuilder};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::adjust::Adjustment;
+use crate::roles::{Db, Moderator};
+use crate::errors::ApiError;
+use crate::pagination::{Pagination, Sort};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "corporate_action_kind", rename_all = "lowercase")]
+pub(crate) enum CorporateActionKind {
+    Split,
+    Dividend,
+}
+
+/// A split or cash dividend taking effect on `ex_date`. `value` is the number
+/// of new shares per old share for a split and the cash per share for a dividend.
+#[derive(Serialize, Debug, FromRow, ToSchema)]
+pub(crate) struct CorporateAction {
+    id: i32,
+    ticker: String,
+    ex_date: NaiveDate,
+    kind: CorporateActionKind,
+    value: Decimal,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CorporateActionBody {
+    ticker: String,
+    ex_date: NaiveDate,
+    kind: CorporateActionKind,
+    value: Decimal,
+}
+
+impl CorporateActionBody {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut problems = Vec::new();
+        if self.value <= Decimal::ZERO {
+            problems.push("value must be positive".to_string());
+        }
+        if self.kind == CorporateActionKind::Split && self.value == Decimal::ONE {
+            problems.push("a split ratio of 1 changes nothing".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(problems))
+        }
+    }
+}
+
+const SORT_COLUMNS: [&str; 5] = ["id", "ticker", "ex_date", "kind", "value"];
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct CorporateActionQuery {
+    ticker: Option<String>,
+    kind: Option<CorporateActionKind>,
+    /// First ex-date to include, `YYYY-MM-DD`.
+    from: Option<NaiveDate>,
+    /// Last ex-date to include, `YYYY-MM-DD`.
+    to: Option<NaiveDate>,
+    /// `id`, `ticker`, `ex_date`, `kind` or `value`, prefixed with `-` for descending order.
+    sort: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+fn push_filters(builder: &mut QueryBuilder<Postgres>, query: &CorporateActionQuery) {
+    if let Some(ticker) = &query.ticker {
+        builder.push(" AND ticker = ").push_bind(ticker.clone());
+    }
+    if let Some(kind) = query.kind {
+        builder.push(" AND kind = ").push_bind(kind);
+    }
+    if let Some(from) = query.from {
+        builder.push(" AND ex_date >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND ex_date <= ").push_bind(to);
+    }
+}
+
+#[derive(FromRow)]
+struct AdjustmentRow {
+    ticker: String,
+    ex_date: NaiveDate,
+    kind: CorporateActionKind,
+    value: Decimal,
+    previous_close: Option<Decimal>,
+}
+
+/// Price and volume factors from every corporate action of `tickers`. Dividends
+/// are related to the raw close of the trading day before their ex-date.
+pub(crate) async fn adjustments(db: &Pool<Postgres>, tickers: &[String]) -> Result<Vec<Adjustment>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, AdjustmentRow>(
+        "SELECT ticker, ex_date, kind, value,
+            (SELECT close FROM ledger
+            WHERE ledger.ticker = corporate_action.ticker AND ledger.date < corporate_action.ex_date
+            ORDER BY date DESC
+            LIMIT 1) AS previous_close
+        FROM corporate_action
+        WHERE ticker = ANY($1)"
+    )
+    .bind(tickers)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter()
+        .filter_map(|row| match row.kind {
+            CorporateActionKind::Split => Some(Adjustment::split(row.ticker, row.ex_date, row.value)),
+            CorporateActionKind::Dividend => Adjustment::dividend(row.ticker, row.ex_date, row.value, row.previous_close),
+        })
+        .collect())
+}
+
+#[utoipa::path(
+    tag = "corporate_actions",
+    params(CorporateActionQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Page of corporate actions", body = CorporateActionPage),
+        (status = 400, description = "Invalid sort column, kind, date or offset", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+#[get("/corporate_actions")]
+async fn fetch_corporate_actions(db: Db, req: HttpRequest, query: Query<CorporateActionQuery>) -> Result<HttpResponse, ApiError> {
+    let query: CorporateActionQuery = query.into_inner();
+    let pagination = Pagination::new(query.limit, query.offset)?;
+    let sort = Sort::parse(query.sort.as_deref(), &SORT_COLUMNS, "ex_date")?;
+
+    let mut count: QueryBuilder<Postgres> = QueryBuilder::new("SELECT COUNT(*) FROM corporate_action WHERE TRUE");
+    push_filters(&mut count, &query);
+    let (total,) = count.build_query_as::<(i64,)>().fetch_one(&*db).await?;
+
+    let mut select: QueryBuilder<Postgres> = QueryBuilder::new("SELECT * FROM corporate_action WHERE TRUE");
+    push_filters(&mut select, &query);
+    sort.push_order_by(&mut select, &["id"]);
+    pagination.push_limit(&mut select);
+    let actions = select.build_query_as::<CorporateAction>().fetch_all(&*db).await?;
+
+    Ok(HttpResponse::Ok().json(pagination.page(&req, actions, total)))
+}
+
+#[utoipa::path(
+    tag = "corporate_actions",
+    request_body = CorporateActionBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Created corporate action", body = CorporateAction),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 409, description = "Ticker already has an action of this kind on the ex-date", body = ErrorBody),
+        (status = 422, description = "Unknown ticker or invalid value", body = ErrorBody),
+    )
+)]
+#[post("/corporate_actions")]
+async fn create_corporate_action(_moderator: Moderator, db: Db, body: Json<CorporateActionBody>) -> Result<HttpResponse, ApiError> {
+    body.validate()?;
+    let action = sqlx::query_as::<_, CorporateAction>(
+        "INSERT INTO corporate_action (ticker, ex_date, kind, value)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *"
+    )
+    .bind(&body.ticker)
+    .bind(body.ex_date)
+    .bind(body.kind)
+    .bind(body.value)
+    .fetch_one(&*db)
+    .await?;
+    Ok(HttpResponse::Ok().json(action))
+}
+
+#[utoipa::path(
+    tag = "corporate_actions",
+    params(("id" = i32, Path, description = "Corporate action id")),
+    request_body = CorporateActionBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Updated corporate action", body = CorporateAction),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Corporate action not found", body = ErrorBody),
+        (status = 409, description = "Ticker already has an action of this kind on the ex-date", body = ErrorBody),
+        (status = 422, description = "Unknown ticker or invalid value", body = ErrorBody),
+    )
+)]
+#[put("/corporate_actions/{id}")]
+async fn update_corporate_action(_moderator: Moderator, db: Db, id: web::Path<i32>, body: Json<CorporateActionBody>) -> Result<HttpResponse, ApiError> {
+    body.validate()?;
+    let action = sqlx::query_as::<_, CorporateAction>(
+        "UPDATE corporate_action
+        SET ticker = $2,
+            ex_date = $3,
+            kind = $4,
+            value = $5
+        WHERE id = $1
+        RETURNING *"
+    )
+    .bind(*id)
+    .bind(&body.ticker)
+    .bind(body.ex_date)
+    .bind(body.kind)
+    .bind(body.value)
+    .fetch_one(&*db)
+    .await?;
+    Ok(HttpResponse::Ok().json(action))
+}
+
+#[utoipa::path(
+    tag = "corporate_actions",
+    params(("id" = i32, Path, description = "Corporate action id")),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Deleted corporate action", body = CorporateAction),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+        (status = 404, description = "Corporate action not found", body = ErrorBody),
+    )
+)]
+#[delete("/corporate_actions/{id}")]
+async fn delete_corporate_action(_moderator: Moderator, db: Db, id: web::Path<i32>) -> Result<HttpResponse, ApiError> {
+    let action = sqlx::query_as::<_, CorporateAction>("DELETE FROM corporate_action WHERE id = $1 RETURNING *")
+    .bind(*id)
+    .fetch_one(&*db)
+    .await?;
+    Ok(HttpResponse::Ok().json(action))
+}
@@ -1,33 +1,50 @@
-use actix_web::{get, web::{Data, ReqData}, Responder, HttpResponse};
+use actix_web::{get, web::Query, HttpRequest, HttpResponse};
 use serde::{Serialize, Deserialize};
-use sqlx::{self, FromRow};
+use sqlx::{self, FromRow, Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::{AppState, TokenClaims};
+use crate::roles::Db;
+use crate::errors::ApiError;
+use crate::pagination::{Pagination, Sort};
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
-struct Exchange {
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub(crate) struct Exchange {
     mic: String,
     name: String,
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ExchangeQuery {
+    /// `mic` or `name`, prefixed with `-` for descending order.
+    sort: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[utoipa::path(
+    tag = "exchange",
+    params(ExchangeQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Page of exchanges", body = ExchangePage),
+        (status = 400, description = "Invalid sort column or offset", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
 #[get("/exchange")]
-async fn fetch_exchange(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, Exchange>("SELECT * FROM exchange")
-            .fetch_all(db)
-            .await
-            {
-                Ok(exchange) => HttpResponse::Ok().json(exchange),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
-    }
+async fn fetch_exchange(db: Db, req: HttpRequest, query: Query<ExchangeQuery>) -> Result<HttpResponse, ApiError> {
+    let pagination = Pagination::new(query.limit, query.offset)?;
+    let sort = Sort::parse(query.sort.as_deref(), &["mic", "name"], "mic")?;
+
+    let (total,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM exchange")
+    .fetch_one(&*db)
+    .await?;
+
+    let mut select: QueryBuilder<Postgres> = QueryBuilder::new("SELECT * FROM exchange");
+    sort.push_order_by(&mut select, &["mic"]);
+    pagination.push_limit(&mut select);
+    let exchanges = select.build_query_as::<Exchange>().fetch_all(&*db).await?;
+
+    Ok(HttpResponse::Ok().json(pagination.page(&req, exchanges, total)))
 }
@@ -0,0 +1,78 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use actix_web::{get, rt::time::timeout, web::Data, HttpResponse};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::AppState;
+
+/// How long a pool may take to answer the readiness query.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Readiness {
+    ready: bool,
+    pools: BTreeMap<&'static str, &'static str>,
+}
+
+/// Liveness probe; answers as long as the process can serve requests.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Process is up", body = String),
+    )
+)]
+#[get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json("ok")
+}
+
+/// Readiness probe; every pool has to run a query within `READINESS_TIMEOUT`.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "All pools answer", body = Readiness),
+        (status = 503, description = "At least one pool is unavailable", body = Readiness),
+    )
+)]
+#[get("/readyz")]
+async fn readyz(state: Data<AppState>) -> HttpResponse {
+    let mut pools = BTreeMap::new();
+    for (name, pool) in state.pools() {
+        let status = match timeout(READINESS_TIMEOUT, sqlx::query("SELECT 1").execute(pool)).await {
+            Ok(Ok(_)) => "ok",
+            Ok(Err(error)) => {
+                log::warn!("readiness check of the {} pool failed: {}", name, error);
+                "unavailable"
+            }
+            Err(_) => {
+                log::warn!("readiness check of the {} pool timed out", name);
+                "timeout"
+            }
+        };
+        pools.insert(name, status);
+    }
+
+    let ready = pools.values().all(|status| *status == "ok");
+    let readiness = Readiness { ready, pools };
+    if ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+/// Prometheus scrape endpoint.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    )
+)]
+#[get("/metrics")]
+async fn metrics(state: Data<AppState>) -> HttpResponse {
+    let body = state.metrics.render(&state.pools(), state.config.database.max_connections);
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
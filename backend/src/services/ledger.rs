@@ -1,72 +1,333 @@
-use actix_web::{get, web::{Data, ReqData, self}, Responder, HttpResponse};
+use std::{collections::{BTreeMap, BTreeSet}, io::Cursor};
+
+use actix_web::{get, web::{self, Data, Query, ReqData}, HttpRequest, HttpResponse};
 use serde::{Serialize, Deserialize};
-use sqlx::{self, FromRow};
+use sqlx::{self, FromRow, Postgres, QueryBuilder};
 use chrono::NaiveDate;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{AppState, TokenClaims};
+use crate::roles::{Admin, Db};
+use crate::errors::ApiError;
+use crate::import;
+use crate::adjust::adjust;
+use crate::indicators::{Indicator, IndicatorName};
+use super::{companies, corporate_actions};
+use crate::pagination::{Pagination, Sort};
+use crate::resample::Interval;
 
-#[derive(Serialize, Debug, Deserialize, FromRow)]
-struct EoD {
-    ticker: String,
-    date: NaiveDate,
-    open: f32,
-    close: f32,
-    volume: f64,
+/// One trading day of a ticker. Prices are exact decimals, serialized as
+/// strings so no precision is lost on the way to the client.
+#[derive(Serialize, Debug, Deserialize, FromRow, ToSchema)]
+pub(crate) struct EoD {
+    pub(crate) ticker: String,
+    pub(crate) date: NaiveDate,
+    pub(crate) open: Decimal,
+    pub(crate) high: Decimal,
+    pub(crate) low: Decimal,
+    pub(crate) close: Decimal,
+    /// Close adjusted for splits and dividends.
+    pub(crate) adj_close: Decimal,
+    pub(crate) volume: i64,
 }
 
-#[get("/ledger")]
-async fn fetch_ledger(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, EoD>("SELECT * FROM ledger LIMIT 5000")
-            .fetch_all(db)
-            .await
-            {
-                Ok(companies) => HttpResponse::Ok().json(companies),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+/// Test bar from whole `[open, high, low, close]` prices, with the adjusted
+/// close equal to the close.
+#[cfg(test)]
+pub(crate) fn bar(ticker: &str, date: &str, [open, high, low, close]: [i64; 4], volume: i64) -> EoD {
+    EoD {
+        ticker: ticker.to_string(),
+        date: date.parse().unwrap(),
+        open: Decimal::from(open),
+        high: Decimal::from(high),
+        low: Decimal::from(low),
+        close: Decimal::from(close),
+        adj_close: Decimal::from(close),
+        volume,
+    }
+}
+
+/// Whether `value` fits a `NUMERIC(20, 6)` column, 14 integer and 6 fractional
+/// digits, without being rounded.
+pub(crate) fn fits_numeric(value: Decimal) -> bool {
+    value.normalize().scale() <= 6 && value.abs() < Decimal::from(100_000_000_000_000_i64)
+}
+
+const SORT_COLUMNS: [&str; 8] = ["ticker", "date", "open", "high", "low", "close", "adj_close", "volume"];
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct LedgerQuery {
+    ticker: Option<String>,
+    /// Exact trading day, `YYYY-MM-DD`.
+    date: Option<NaiveDate>,
+    /// First trading day to include.
+    from: Option<NaiveDate>,
+    /// Last trading day to include.
+    to: Option<NaiveDate>,
+    /// `ticker`, `date`, `open`, `high`, `low`, `close`, `adj_close` or `volume`, prefixed with `-` for descending order.
+    /// Sorting and filtering use raw prices even when `adjusted` is set.
+    sort: Option<String>,
+    /// Back-adjust prices and volume for splits and dividends.
+    #[serde(default)]
+    adjusted: bool,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// Date order of a single ticker's history.
+#[derive(Deserialize, ToSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct LedgerRangeQuery {
+    /// First trading day to include, `YYYY-MM-DD`.
+    from: Option<NaiveDate>,
+    /// Last trading day to include, `YYYY-MM-DD`.
+    to: Option<NaiveDate>,
+    /// Oldest first unless `desc`.
+    #[serde(default)]
+    order: Order,
+    /// Merge daily bars into `week`, `month`, `quarter` or `year` bars.
+    #[serde(default)]
+    interval: Interval,
+    /// Back-adjust prices and volume for splits and dividends.
+    #[serde(default)]
+    adjusted: bool,
+}
+
+/// `SELECT {columns} FROM ledger` restricted to the rows `query` filters for.
+/// Both the page and its total are built from this so they always agree.
+fn filtered<'a>(columns: &str, query: &LedgerQuery) -> QueryBuilder<'a, Postgres> {
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM ledger WHERE TRUE", columns));
+    if let Some(ticker) = &query.ticker {
+        builder.push(" AND ticker = ").push_bind(ticker.clone());
+    }
+    if let Some(date) = query.date {
+        builder.push(" AND date = ").push_bind(date);
     }
+    if let Some(from) = query.from {
+        builder.push(" AND date >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND date <= ").push_bind(to);
+    }
+    builder
 }
 
-#[get("/ledger_test")]
-async fn fetch_ledg_test(state: Data<AppState>,) -> impl Responder{
-    match sqlx::query_as::<_, EoD>("SELECT * FROM ledger LIMIT 10")
-    .fetch_all(&state.db_admin)
-    .await
-    {
-        Ok(eod) => HttpResponse::Ok().json(eod),
-        Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
+#[utoipa::path(
+    tag = "ledger",
+    params(LedgerQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Page of end of day prices", body = EoDPage),
+        (status = 400, description = "Invalid sort column, date or offset", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+#[get("/ledger")]
+async fn fetch_ledger(db: Db, req: HttpRequest, query: Query<LedgerQuery>) -> Result<HttpResponse, ApiError> {
+    let query: LedgerQuery = query.into_inner();
+    let pagination = Pagination::new(query.limit, query.offset)?;
+    let sort = Sort::parse(query.sort.as_deref(), &SORT_COLUMNS, "ticker")?;
+
+    let (total,) = filtered("COUNT(*)", &query).build_query_as::<(i64,)>().fetch_one(&*db).await?;
+
+    let mut select = filtered("*", &query);
+    sort.push_order_by(&mut select, &["ticker", "date"]);
+    pagination.push_limit(&mut select);
+    let mut prices = select.build_query_as::<EoD>().fetch_all(&*db).await?;
+
+    if query.adjusted {
+        let tickers: Vec<String> = prices.iter().map(|bar| bar.ticker.clone()).collect::<BTreeSet<_>>().into_iter().collect();
+        adjust(&mut prices, &corporate_actions::adjustments(&db, &tickers).await?);
     }
+    Ok(HttpResponse::Ok().json(pagination.page(&req, prices, total)))
 }
 
+#[utoipa::path(
+    tag = "ledger",
+    params(("ticker" = String, Path, description = "Ticker symbol"), LedgerRangeQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Bars of the ticker in date order", body = [EoD]),
+        (status = 400, description = "Malformed date, unknown interval or `from` after `to`", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 404, description = "Unknown ticker", body = ErrorBody),
+    )
+)]
+/// History of one ticker between `from` and `to`, both inclusive, optionally
+/// adjusted for corporate actions and resampled to wider bars. The range is served from the `(ticker, date)`
+/// primary key index.
 #[get("/ledger/{ticker}")]
-async fn fetch_ledger_by_ticker(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>, ticker: web::Path<String>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, EoD>("SELECT * FROM ledger WHERE ticker = $1")
-            .bind(ticker.clone())
-            .fetch_all(db)
-            .await
-            {
-                Ok(companies) => HttpResponse::Ok().json(companies),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
+async fn fetch_ledger_by_ticker(db: Db, ticker: web::Path<String>, query: Query<LedgerRangeQuery>) -> Result<HttpResponse, ApiError> {
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(ApiError::BadRequest("`from` must not be after `to`".to_string()));
+        }
+    }
+
+    let mut select: QueryBuilder<Postgres> = QueryBuilder::new("SELECT * FROM ledger WHERE ticker = ");
+    select.push_bind(ticker.clone());
+    if let Some(from) = query.from {
+        select.push(" AND date >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        select.push(" AND date <= ").push_bind(to);
+    }
+    select.push(" ORDER BY date ASC");
+    let mut prices = select.build_query_as::<EoD>().fetch_all(&*db).await?;
+    if query.adjusted {
+        adjust(&mut prices, &corporate_actions::adjustments(&db, std::slice::from_ref(&*ticker)).await?);
+    }
+    let mut prices = query.interval.resample(prices);
+    if query.order == Order::Desc {
+        prices.reverse();
+    }
+
+    if prices.is_empty() {
+        companies::ensure_company(&db, &ticker).await?;
+    }
+    Ok(HttpResponse::Ok().json(prices))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct IndicatorQuery {
+    name: IndicatorName,
+    /// Comma separated parameters, e.g. `12,26,9` for MACD. Missing ones take their defaults.
+    params: Option<String>,
+    /// First trading day to return; earlier prices still count towards the indicator.
+    from: Option<NaiveDate>,
+    /// Last trading day to include, `YYYY-MM-DD`.
+    to: Option<NaiveDate>,
+    /// Compute on closes adjusted for splits and dividends.
+    #[serde(default)]
+    adjusted: bool,
+}
+
+/// Indicator values of one trading day, keyed by output name such as `value`,
+/// `macd` or `upper`.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct IndicatorPoint {
+    date: NaiveDate,
+    values: BTreeMap<&'static str, f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct IndicatorSeries {
+    ticker: String,
+    name: IndicatorName,
+    points: Vec<IndicatorPoint>,
+}
+
+#[utoipa::path(
+    tag = "ledger",
+    params(("ticker" = String, Path, description = "Ticker symbol"), IndicatorQuery),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Indicator values in date order, from the first day with enough history", body = IndicatorSeries),
+        (status = 400, description = "Unknown indicator, invalid parameters or dates", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 404, description = "Unknown ticker", body = ErrorBody),
+    )
+)]
+/// Computes an indicator on the daily close. The whole history up to `to` is
+/// read so that `from` does not shorten the warm-up period.
+#[get("/ledger/{ticker}/indicators")]
+async fn fetch_indicators(db: Db, ticker: web::Path<String>, query: Query<IndicatorQuery>) -> Result<HttpResponse, ApiError> {
+    let indicator = Indicator::new(query.name, query.params.as_deref()).map_err(ApiError::BadRequest)?;
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(ApiError::BadRequest("`from` must not be after `to`".to_string()));
         }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
     }
+
+    let mut select: QueryBuilder<Postgres> = QueryBuilder::new("SELECT * FROM ledger WHERE ticker = ");
+    select.push_bind(ticker.clone());
+    if let Some(to) = query.to {
+        select.push(" AND date <= ").push_bind(to);
+    }
+    select.push(" ORDER BY date ASC");
+    let mut prices = select.build_query_as::<EoD>().fetch_all(&*db).await?;
+    if prices.is_empty() {
+        companies::ensure_company(&db, &ticker).await?;
+    }
+    if query.adjusted {
+        adjust(&mut prices, &corporate_actions::adjustments(&db, std::slice::from_ref(&*ticker)).await?);
+    }
+
+    let closes: Vec<f64> = prices.iter().map(|bar| bar.close.to_f64().unwrap_or(f64::NAN)).collect();
+    let points = prices.iter().zip(indicator.compute(&closes))
+        .filter(|(bar, _)| query.from.is_none_or(|from| bar.date >= from))
+        .filter_map(|(bar, values)| Some(IndicatorPoint {
+            date: bar.date,
+            values: indicator.outputs().iter().copied().zip(values?).collect(),
+        }))
+        .collect();
+    Ok(HttpResponse::Ok().json(IndicatorSeries { ticker: ticker.into_inner(), name: query.name, points }))
+}
+
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ImportQuery {
+    /// Ticker of every row, for files without a ticker column.
+    ticker: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/ledger/import",
+    tag = "ledger",
+    params(ImportQuery),
+    request_body(content = String, content_type = "text/csv", description = "Yahoo or Stooq style CSV, optionally with a ticker column"),
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Rows inserted, updated and rejected", body = ImportReport),
+        (status = 400, description = "Unreadable header, missing columns or no ticker", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 403, description = "Insufficient privileges", body = ErrorBody),
+    )
+)]
+/// Upserts end of day prices from a CSV body, see `import::import_csv`.
+/// Routed in `main` rather than with `#[post]`, to give it a larger body limit.
+pub(crate) async fn import_ledger(_admin: Admin, state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, query: Query<ImportQuery>, body: web::Bytes) -> Result<HttpResponse, ApiError> {
+    let report = import::import_csv(&db, Cursor::new(body), query.ticker.as_deref()).await?;
+    import::record_import(&state.db_auth, Some(user.id), "upload", &report).await;
+    Ok(HttpResponse::Ok().json(report))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_applies_the_same_filters_as_the_page() {
+        let query = LedgerQuery {
+            ticker: Some("AAPL".to_string()),
+            date: None,
+            from: NaiveDate::from_ymd_opt(2023, 1, 1),
+            to: NaiveDate::from_ymd_opt(2023, 12, 31),
+            sort: None,
+            adjusted: false,
+            limit: None,
+            offset: None,
+        };
+        assert_eq!(
+            filtered("COUNT(*)", &query).sql(),
+            "SELECT COUNT(*) FROM ledger WHERE TRUE AND ticker = $1 AND date >= $2 AND date <= $3",
+        );
+        assert_eq!(
+            filtered("*", &query).sql(),
+            "SELECT * FROM ledger WHERE TRUE AND ticker = $1 AND date >= $2 AND date <= $3",
+        );
+    }
+}
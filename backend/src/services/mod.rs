@@ -1,6 +1,14 @@
 pub mod accounts;
+pub mod analytics;
 pub mod companies;
+pub mod corporate_actions;
 pub mod ledger;
 pub mod watch_list;
 pub mod portfolio;
 pub mod exchange;
+pub mod api_keys;
+pub mod audit_log;
+pub mod docs;
+pub mod health;
+#[cfg(feature = "test-routes")]
+pub mod test_routes;
@@ -1,145 +1,243 @@
-use actix_web::{get, post, web::{Data, ReqData, Json}, Responder, HttpResponse, delete, patch};
+use actix_web::{get, post, web::{Data, ReqData, Json}, HttpResponse, delete, patch};
 use serde::{Serialize, Deserialize};
+use serde_json::json;
 use sqlx::{self, FromRow};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use utoipa::ToSchema;
 
 use crate::{AppState, TokenClaims};
+use crate::audit::{self, AuditAction};
+use crate::roles::Db;
+use crate::errors::ApiError;
+use super::ledger::fits_numeric;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
-struct PortfolioItem {
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub(crate) struct PortfolioItem {
     account_id: i32,
     ticker: String,
-    amount: f32,
-    buy_price: f32,
+    amount: Decimal,
+    buy_price: Decimal,
 }
 
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
-struct PortfolioItemBody {
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub(crate) struct PortfolioItemBody {
     ticker: String,
-    amount: f32,
-    buy_price: f32,
+    /// At most 14 integer and 6 fractional digits, like `buy_price`.
+    amount: Decimal,
+    buy_price: Decimal,
 }
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
-struct DeletePortfolioItem {
-    ticker: String,
+impl PortfolioItemBody {
+    fn validate(&self) -> Result<(), ApiError> {
+        let problems: Vec<String> = [("amount", self.amount), ("buy_price", self.buy_price)].into_iter()
+            .filter(|(_, value)| !fits_numeric(*value))
+            .map(|(name, _)| format!("{} must have at most 14 integer and 6 fractional digits", name))
+            .collect();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(problems))
+        }
+    }
 }
 
-#[get("/portfolio_test")]
-async fn fetch_portfolio_test(state: Data<AppState>) -> impl Responder{
-    match sqlx::query_as::<_, PortfolioItem>("SELECT * FROM portfolio LIMIT 1")
-    .fetch_all(&state.db_admin)
-    .await
-    {
-        Ok(eod) => HttpResponse::Ok().json(eod),
-        Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-    }
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub(crate) struct DeletePortfolioItem {
+    ticker: String,
 }
 
+#[utoipa::path(
+    tag = "portfolio",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Portfolio of the caller", body = [PortfolioItem]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
 #[get("/portfolio")]
-async fn fetch_portfolio(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>) -> impl Responder {
+async fn fetch_portfolio(db: Db, user: ReqData<TokenClaims>) -> Result<HttpResponse, ApiError> {
+    let portfolio = sqlx::query_as::<_, PortfolioItem>("SELECT * FROM portfolio WHERE account_id = $1")
+    .bind(user.id)
+    .fetch_all(&*db)
+    .await?;
+    Ok(HttpResponse::Ok().json(portfolio))
+}
+
+#[derive(FromRow)]
+struct PricedHolding {
+    ticker: String,
+    amount: Decimal,
+    buy_price: Decimal,
+    price_date: Option<NaiveDate>,
+    close: Option<Decimal>,
+}
+
+/// One holding valued at the latest close in the ledger. The price fields are
+/// `null` while the ticker has no prices yet.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct HoldingValuation {
+    ticker: String,
+    amount: Decimal,
+    buy_price: Decimal,
+    cost_basis: Decimal,
+    /// Trading day of `close`.
+    price_date: Option<NaiveDate>,
+    close: Option<Decimal>,
+    market_value: Option<Decimal>,
+    gain: Option<Decimal>,
+}
+
+/// Totals only cover holdings that have a price.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct PortfolioValuation {
+    holdings: Vec<HoldingValuation>,
+    cost_basis: Decimal,
+    market_value: Decimal,
+    gain: Decimal,
+}
 
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, PortfolioItem>("SELECT * FROM portfolio WHERE account_id = $1")
-            .bind(user.id)
-            .fetch_all(db)
-            .await
-            {
-                Ok(portfolio) => HttpResponse::Ok().json(portfolio),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
+impl PortfolioValuation {
+    fn new(priced: Vec<PricedHolding>) -> PortfolioValuation {
+        let mut valuation = PortfolioValuation {
+            holdings: Vec::with_capacity(priced.len()),
+            cost_basis: Decimal::ZERO,
+            market_value: Decimal::ZERO,
+            gain: Decimal::ZERO,
+        };
+        for holding in priced {
+            let cost_basis = holding.amount * holding.buy_price;
+            let market_value = holding.close.map(|close| holding.amount * close);
+            if let Some(market_value) = market_value {
+                valuation.cost_basis += cost_basis;
+                valuation.market_value += market_value;
             }
+            valuation.holdings.push(HoldingValuation {
+                ticker: holding.ticker,
+                amount: holding.amount,
+                buy_price: holding.buy_price,
+                cost_basis,
+                price_date: holding.price_date,
+                close: holding.close,
+                market_value,
+                gain: market_value.map(|market_value| market_value - cost_basis),
+            });
         }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+        valuation.gain = valuation.market_value - valuation.cost_basis;
+        valuation
     }
 }
 
+#[utoipa::path(
+    tag = "portfolio",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Holdings of the caller valued at their latest close", body = PortfolioValuation),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
+#[get("/portfolio/valuation")]
+async fn fetch_portfolio_valuation(db: Db, user: ReqData<TokenClaims>) -> Result<HttpResponse, ApiError> {
+    // Each lateral lookup reads one row off the end of the (ticker, date) primary key.
+    let priced = sqlx::query_as::<_, PricedHolding>(
+        "SELECT portfolio.ticker, portfolio.amount, portfolio.buy_price,
+            latest.date AS price_date, latest.close
+        FROM portfolio
+        LEFT JOIN LATERAL (
+            SELECT date, close FROM ledger
+            WHERE ledger.ticker = portfolio.ticker
+            ORDER BY date DESC
+            LIMIT 1
+        ) latest ON TRUE
+        WHERE portfolio.account_id = $1
+        ORDER BY portfolio.ticker"
+    )
+    .bind(user.id)
+    .fetch_all(&*db)
+    .await?;
+    Ok(HttpResponse::Ok().json(PortfolioValuation::new(priced)))
+}
+
+#[utoipa::path(
+    tag = "portfolio",
+    request_body = PortfolioItemBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Added item", body = [PortfolioItem]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 409, description = "Ticker already in the portfolio", body = ErrorBody),
+        (status = 422, description = "Unknown ticker or too precise amount or price", body = ErrorBody),
+    )
+)]
 #[post("/portfolio_item")]
-async fn post_portfolio_item(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>, body: Json<PortfolioItemBody>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            let portfolio_item_body: PortfolioItemBody = body.into_inner();
-            match sqlx::query_as::<_, PortfolioItem>("INSERT INTO portfolio VALUES ($1, $2, $3, $4) RETURNING account_id, ticker, amount, buy_price")
-            .bind(user.id)
-            .bind(portfolio_item_body.ticker)
-            .bind(portfolio_item_body.amount)
-            .bind(portfolio_item_body.buy_price)
-            .fetch_all(db)
-            .await
-            {
-                Ok(portfolioitem) => HttpResponse::Ok().json(portfolioitem),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+async fn post_portfolio_item(state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, body: Json<PortfolioItemBody>) -> Result<HttpResponse, ApiError> {
+    let portfolio_item_body: PortfolioItemBody = body.into_inner();
+    portfolio_item_body.validate()?;
+    let portfolioitem = sqlx::query_as::<_, PortfolioItem>("INSERT INTO portfolio VALUES ($1, $2, $3, $4) RETURNING account_id, ticker, amount, buy_price")
+    .bind(user.id)
+    .bind(portfolio_item_body.ticker)
+    .bind(portfolio_item_body.amount)
+    .bind(portfolio_item_body.buy_price)
+    .fetch_all(&*db)
+    .await?;
+    if !portfolioitem.is_empty() {
+        audit::record(&state.db_auth, AuditAction::PortfolioItemAdded, Some(user.id), Some(user.id), json!(portfolioitem)).await;
     }
+    Ok(HttpResponse::Ok().json(portfolioitem))
 }
 
+#[utoipa::path(
+    tag = "portfolio",
+    request_body = DeletePortfolioItem,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Removed items", body = [PortfolioItem]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
 #[delete("/portfolio_item")]
-async fn delete_portfolio_item(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>, body: Json<DeletePortfolioItem>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            let portfolio_item_body: DeletePortfolioItem = body.into_inner();
-            match sqlx::query_as::<_, PortfolioItem>("DELETE FROM portfolio WHERE account_id = $1 AND ticker = $2 RETURNING * ")
-            .bind(user.id)
-            .bind(portfolio_item_body.ticker)
-            .fetch_all(db)
-            .await
-            {
-                Ok(portfolioitem) => HttpResponse::Ok().json(portfolioitem),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+async fn delete_portfolio_item(state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, body: Json<DeletePortfolioItem>) -> Result<HttpResponse, ApiError> {
+    let portfolio_item_body: DeletePortfolioItem = body.into_inner();
+    let portfolioitem = sqlx::query_as::<_, PortfolioItem>("DELETE FROM portfolio WHERE account_id = $1 AND ticker = $2 RETURNING * ")
+    .bind(user.id)
+    .bind(portfolio_item_body.ticker)
+    .fetch_all(&*db)
+    .await?;
+    if !portfolioitem.is_empty() {
+        audit::record(&state.db_auth, AuditAction::PortfolioItemRemoved, Some(user.id), Some(user.id), json!(portfolioitem)).await;
     }
+    Ok(HttpResponse::Ok().json(portfolioitem))
 }
 
+#[utoipa::path(
+    tag = "portfolio",
+    request_body = PortfolioItemBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Updated items", body = [PortfolioItem]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 422, description = "Too precise amount or price", body = ErrorBody),
+    )
+)]
 #[patch("/portfolio_item")]
-async fn alter_portfolio_item(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>, body: Json<PortfolioItemBody>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            let portfolio_item_body: PortfolioItemBody = body.into_inner();
-            match sqlx::query_as::<_, PortfolioItem>("UPDATE portfolio
-                SET amount = $3,
-                    buy_price = $4
-                WHERE account_id = $1 AND ticker = $2
-                RETURNING * "
-            )
-            .bind(user.id)
-            .bind(portfolio_item_body.ticker)
-            .bind(portfolio_item_body.amount)
-            .bind(portfolio_item_body.buy_price)
-            .fetch_all(db)
-            .await
-            {
-                Ok(portfolioitem) => HttpResponse::Ok().json(portfolioitem),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+async fn alter_portfolio_item(state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, body: Json<PortfolioItemBody>) -> Result<HttpResponse, ApiError> {
+    let portfolio_item_body: PortfolioItemBody = body.into_inner();
+    portfolio_item_body.validate()?;
+    let portfolioitem = sqlx::query_as::<_, PortfolioItem>("UPDATE portfolio
+        SET amount = $3,
+            buy_price = $4
+        WHERE account_id = $1 AND ticker = $2
+        RETURNING * "
+    )
+    .bind(user.id)
+    .bind(portfolio_item_body.ticker)
+    .bind(portfolio_item_body.amount)
+    .bind(portfolio_item_body.buy_price)
+    .fetch_all(&*db)
+    .await?;
+    if !portfolioitem.is_empty() {
+        audit::record(&state.db_auth, AuditAction::PortfolioItemAltered, Some(user.id), Some(user.id), json!(portfolioitem)).await;
     }
+    Ok(HttpResponse::Ok().json(portfolioitem))
 }
@@ -0,0 +1,44 @@
+//! Unauthenticated sample endpoints used while developing against a seeded
+//! database. They read through the admin pool, so they are only compiled in
+//! with the `test-routes` feature and never ship in production builds.
+
+use actix_web::{get, web::Data, HttpResponse};
+
+use crate::AppState;
+use crate::errors::ApiError;
+use super::companies::Company;
+use super::ledger::EoD;
+use super::portfolio::PortfolioItem;
+use super::watch_list::WatchItem;
+
+#[get("/companies_test")]
+async fn fetch_comp_test(state: Data<AppState>,) -> Result<HttpResponse, ApiError> {
+    let companies = sqlx::query_as::<_, Company>("SELECT * FROM company LIMIT 5")
+    .fetch_all(&state.db_admin)
+    .await?;
+    Ok(HttpResponse::Ok().json(companies))
+}
+
+#[get("/ledger_test")]
+async fn fetch_ledg_test(state: Data<AppState>,) -> Result<HttpResponse, ApiError> {
+    let eod = sqlx::query_as::<_, EoD>("SELECT * FROM ledger LIMIT 10")
+    .fetch_all(&state.db_admin)
+    .await?;
+    Ok(HttpResponse::Ok().json(eod))
+}
+
+#[get("/watchlist_test")]
+async fn fetch_watch_test(state: Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let eod = sqlx::query_as::<_, WatchItem>("SELECT * FROM watch_list LIMIT 1")
+    .fetch_all(&state.db_admin)
+    .await?;
+    Ok(HttpResponse::Ok().json(eod))
+}
+
+#[get("/portfolio_test")]
+async fn fetch_portfolio_test(state: Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let eod = sqlx::query_as::<_, PortfolioItem>("SELECT * FROM portfolio LIMIT 1")
+    .fetch_all(&state.db_admin)
+    .await?;
+    Ok(HttpResponse::Ok().json(eod))
+}
@@ -1,103 +1,88 @@
-use actix_web::{get, post, web::{Data, ReqData, Json}, Responder, HttpResponse, delete};
+use actix_web::{get, post, web::{Data, ReqData, Json}, HttpResponse, delete};
 use serde::{Serialize, Deserialize};
+use serde_json::json;
 use sqlx::{self, FromRow};
+use utoipa::ToSchema;
 
 use crate::{AppState, TokenClaims};
+use crate::audit::{self, AuditAction};
+use crate::roles::Db;
+use crate::errors::ApiError;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
-struct WatchItem {
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub(crate) struct WatchItem {
     account_id: i32,
     ticker: String,
 }
 
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
-struct WatchItemBody {
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub(crate) struct WatchItemBody {
     ticker: String,
 }
 
 
-#[get("/watchlist_test")]
-async fn fetch_watch_test(state: Data<AppState>) -> impl Responder{
-    match sqlx::query_as::<_, WatchItem>("SELECT * FROM watch_list LIMIT 1")
-    .fetch_all(&state.db_admin)
-    .await
-    {
-        Ok(eod) => HttpResponse::Ok().json(eod),
-        Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-    }
-}
-
+#[utoipa::path(
+    tag = "watch_list",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Watch list of the caller", body = [WatchItem]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
 #[get("/watchlist")]
-async fn fetch_watch_list(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>) -> impl Responder {
-
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            match sqlx::query_as::<_, WatchItem>("SELECT * FROM watch_list WHERE account_id = $1")
-            .bind(user.id)
-            .fetch_all(db)
-            .await
-            {
-                Ok(watchlist) => HttpResponse::Ok().json(watchlist),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
-    }
+async fn fetch_watch_list(db: Db, user: ReqData<TokenClaims>) -> Result<HttpResponse, ApiError> {
+    let watchlist = sqlx::query_as::<_, WatchItem>("SELECT * FROM watch_list WHERE account_id = $1")
+    .bind(user.id)
+    .fetch_all(&*db)
+    .await?;
+    Ok(HttpResponse::Ok().json(watchlist))
 }
 
+#[utoipa::path(
+    tag = "watch_list",
+    request_body = WatchItemBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Added item", body = [WatchItem]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+        (status = 409, description = "Ticker already watched", body = ErrorBody),
+        (status = 422, description = "Unknown ticker", body = ErrorBody),
+    )
+)]
 #[post("/watchitem")]
-async fn post_watchitem(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>, body: Json<WatchItemBody>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            let watchitem_body: WatchItemBody = body.into_inner();
-            match sqlx::query_as::<_, WatchItem>("INSERT INTO watch_list VALUES ($1, $2) RETURNING account_id, ticker")
-            .bind(user.id)
-            .bind(watchitem_body.ticker)
-            .fetch_all(db)
-            .await
-            {
-                Ok(watchlist) => HttpResponse::Ok().json(watchlist),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+async fn post_watchitem(state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, body: Json<WatchItemBody>) -> Result<HttpResponse, ApiError> {
+    let watchitem_body: WatchItemBody = body.into_inner();
+    let watchlist = sqlx::query_as::<_, WatchItem>("INSERT INTO watch_list VALUES ($1, $2) RETURNING account_id, ticker")
+    .bind(user.id)
+    .bind(watchitem_body.ticker)
+    .fetch_all(&*db)
+    .await?;
+    if !watchlist.is_empty() {
+        audit::record(&state.db_auth, AuditAction::WatchItemAdded, Some(user.id), Some(user.id), json!(watchlist)).await;
     }
+    Ok(HttpResponse::Ok().json(watchlist))
 }
 
+#[utoipa::path(
+    tag = "watch_list",
+    request_body = WatchItemBody,
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Removed items", body = [WatchItem]),
+        (status = 401, description = "Missing, invalid or revoked credentials", body = ErrorBody),
+    )
+)]
 #[delete("/watchitem")]
-async fn delete_watch_item(state: Data<AppState>, req_user: Option<ReqData<TokenClaims>>, body: Json<WatchItemBody>) -> impl Responder {
-    match req_user {
-        Some(user) => {
-            let db = match user.security_lvl {
-                0 => &state.db_admin,
-                1 => &state.db_moderator,
-                2 => &state.db_user,
-                _ => &state.db_auth
-            };
-            let watch_item_body: WatchItemBody = body.into_inner();
-            match sqlx::query_as::<_, WatchItem>("DELETE FROM portfolio WHERE account_id = $1 AND ticker = $2 RETURNING * ")
-            .bind(user.id)
-            .bind(watch_item_body.ticker)
-            .fetch_all(db)
-            .await
-            {
-                Ok(portfolioitem) => HttpResponse::Ok().json(portfolioitem),
-                Err(error) => HttpResponse::InternalServerError().json(format!("{:?}", error)),
-            }
-        }
-        _ => HttpResponse::Unauthorized().json("Unable to verify identity"),
+async fn delete_watch_item(state: Data<AppState>, db: Db, user: ReqData<TokenClaims>, body: Json<WatchItemBody>) -> Result<HttpResponse, ApiError> {
+    let watch_item_body: WatchItemBody = body.into_inner();
+    let watchlist = sqlx::query_as::<_, WatchItem>("DELETE FROM watch_list WHERE account_id = $1 AND ticker = $2 RETURNING * ")
+    .bind(user.id)
+    .bind(watch_item_body.ticker)
+    .fetch_all(&*db)
+    .await?;
+    if !watchlist.is_empty() {
+        audit::record(&state.db_auth, AuditAction::WatchItemRemoved, Some(user.id), Some(user.id), json!(watchlist)).await;
     }
+    Ok(HttpResponse::Ok().json(watchlist))
 }
@@ -0,0 +1,172 @@
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::services::ledger::EoD;
+
+/// Trading days per year, used to annualise volatility.
+const TRADING_DAYS: f64 = 252.0;
+
+/// History needed before `as_of`: five years for the longest return plus a
+/// margin for the trading day on or before its start.
+pub fn history_start(as_of: NaiveDate) -> NaiveDate {
+    as_of.checked_sub_months(Months::new(60)).unwrap_or(NaiveDate::MIN) - Duration::days(14)
+}
+
+/// Returns as fractions (`0.05` is 5 %), based on `adj_close`: total returns
+/// when adjusted for corporate actions, price returns otherwise.
+/// `null` when the ledger does not reach back far enough.
+#[derive(Serialize, ToSchema)]
+pub struct PeriodReturns {
+    #[serde(rename = "1d")]
+    one_day: Option<f64>,
+    #[serde(rename = "1w")]
+    one_week: Option<f64>,
+    #[serde(rename = "1m")]
+    one_month: Option<f64>,
+    /// Since the last close of the previous year.
+    ytd: Option<f64>,
+    #[serde(rename = "1y")]
+    one_year: Option<f64>,
+    #[serde(rename = "5y")]
+    five_years: Option<f64>,
+}
+
+/// Statistics of a ticker as of one trading day. Everything except the returns
+/// covers the 52 weeks ending on `as_of`.
+#[derive(Serialize, ToSchema)]
+pub struct CompanyStats {
+    ticker: String,
+    /// Last trading day on or before the requested date.
+    as_of: NaiveDate,
+    close: Decimal,
+    adj_close: Decimal,
+    returns: PeriodReturns,
+    /// Annualised sample standard deviation of daily log returns.
+    volatility: Option<f64>,
+    /// Largest decline from a running peak of the adjusted close, as a positive fraction.
+    max_drawdown: f64,
+    high_52w: Decimal,
+    low_52w: Decimal,
+    average_volume: f64,
+}
+
+/// Adjusted close of the last bar on or before `date`.
+fn adj_close_on(bars: &[EoD], date: NaiveDate) -> Option<Decimal> {
+    let index = bars.partition_point(|bar| bar.date <= date);
+    index.checked_sub(1).map(|index| bars[index].adj_close)
+}
+
+fn change(from: Option<Decimal>, to: Decimal) -> Option<f64> {
+    let from = from.filter(|from| !from.is_zero())?;
+    (to / from - Decimal::ONE).to_f64()
+}
+
+/// Computes the statistics from bars in ascending date order, the last one
+/// being the `as_of` day. Returns `None` without bars.
+pub fn compute(bars: &[EoD]) -> Option<CompanyStats> {
+    let last = bars.last()?;
+    let as_of = last.date;
+    let months_back = |months: u32| as_of.checked_sub_months(Months::new(months)).unwrap_or(NaiveDate::MIN);
+    let previous_year_end = NaiveDate::from_ymd_opt(as_of.year() - 1, 12, 31).unwrap_or(NaiveDate::MIN);
+
+    let returns = PeriodReturns {
+        one_day: change(bars.len().checked_sub(2).map(|index| bars[index].adj_close), last.adj_close),
+        one_week: change(adj_close_on(bars, as_of - Duration::days(7)), last.adj_close),
+        one_month: change(adj_close_on(bars, months_back(1)), last.adj_close),
+        ytd: change(adj_close_on(bars, previous_year_end), last.adj_close),
+        one_year: change(adj_close_on(bars, months_back(12)), last.adj_close),
+        five_years: change(adj_close_on(bars, months_back(60)), last.adj_close),
+    };
+
+    let year = &bars[bars.partition_point(|bar| bar.date <= months_back(12))..];
+    let closes: Vec<f64> = year.iter().filter_map(|bar| bar.adj_close.to_f64()).collect();
+
+    let log_returns: Vec<f64> = closes.windows(2).map(|pair| (pair[1] / pair[0]).ln()).collect();
+    let volatility = (log_returns.len() >= 2).then(|| {
+        let mean = log_returns.iter().sum::<f64>() / log_returns.len() as f64;
+        let variance = log_returns.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (log_returns.len() - 1) as f64;
+        (variance * TRADING_DAYS).sqrt()
+    });
+
+    let mut peak = f64::MIN;
+    let mut max_drawdown: f64 = 0.0;
+    for close in &closes {
+        peak = peak.max(*close);
+        max_drawdown = max_drawdown.max((peak - close) / peak);
+    }
+
+    Some(CompanyStats {
+        ticker: last.ticker.clone(),
+        as_of,
+        close: last.close,
+        adj_close: last.adj_close,
+        returns,
+        volatility,
+        max_drawdown,
+        high_52w: year.iter().map(|bar| bar.high).max().unwrap_or(last.high),
+        low_52w: year.iter().map(|bar| bar.low).min().unwrap_or(last.low),
+        average_volume: year.iter().map(|bar| bar.volume as f64).sum::<f64>() / year.len() as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ledger::bar;
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("value missing");
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn history_reaches_back_five_years() {
+        let as_of: NaiveDate = "2023-03-15".parse().unwrap();
+        assert_eq!(history_start(as_of).to_string(), "2018-03-01");
+    }
+
+    #[test]
+    fn short_history() {
+        let bars = [
+            bar("TEST", "2022-12-29", [80, 81, 79, 80], 100),
+            bar("TEST", "2023-01-02", [100, 101, 99, 100], 200),
+            bar("TEST", "2023-01-03", [110, 111, 109, 110], 300),
+            bar("TEST", "2023-01-04", [99, 100, 98, 99], 400),
+            bar("TEST", "2023-01-05", [121, 122, 120, 121], 500),
+        ];
+        let stats = compute(&bars).unwrap();
+        assert_eq!(stats.as_of.to_string(), "2023-01-05");
+        assert_close(stats.returns.one_day, 121.0 / 99.0 - 1.0);
+        assert_close(stats.returns.one_week, 121.0 / 80.0 - 1.0);
+        assert_close(stats.returns.ytd, 121.0 / 80.0 - 1.0);
+        assert!(stats.returns.one_month.is_none());
+        assert!(stats.returns.one_year.is_none());
+        assert!(stats.returns.five_years.is_none());
+        // Sample deviation of ln(100/80), ln(1.1), ln(0.9), ln(121/99), times sqrt(252).
+        assert_close(stats.volatility, 2.3802323001134855);
+        assert_close(Some(stats.max_drawdown), 0.1);
+        assert_eq!(stats.high_52w, Decimal::from(122));
+        assert_eq!(stats.low_52w, Decimal::from(79));
+        assert_close(Some(stats.average_volume), 300.0);
+    }
+
+    #[test]
+    fn long_returns_use_the_last_close_on_or_before_the_start() {
+        let bars = [bar("TEST", "2018-01-05", [50, 51, 49, 50], 1), bar("TEST", "2023-01-05", [100, 101, 99, 100], 1)];
+        let stats = compute(&bars).unwrap();
+        assert_close(stats.returns.five_years, 1.0);
+        assert_close(stats.returns.one_year, 1.0);
+        assert_close(stats.returns.one_day, 1.0);
+        // Only the last bar lies within the 52 weeks.
+        assert!(stats.volatility.is_none());
+        assert_eq!(stats.max_drawdown, 0.0);
+        assert_eq!(stats.low_52w, Decimal::from(99));
+    }
+
+    #[test]
+    fn no_bars() {
+        assert!(compute(&[]).is_none());
+    }
+}
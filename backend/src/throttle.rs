@@ -0,0 +1,278 @@
+use std::{collections::HashMap, net::{IpAddr, SocketAddr}, sync::Mutex, time::{Duration, Instant}};
+
+use actix_web::HttpRequest;
+
+use crate::config::{LoginThrottleConfig, ProxyHeader, ServerConfig};
+
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Instant,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Blocked {
+    /// Too many recent failures, retry after the backoff delay.
+    Backoff(Duration),
+    /// Failure limit reached, the key is locked out for a while.
+    Locked(Duration),
+}
+
+/// Address of the client, used to key the throttle. Behind a load balancer every
+/// peer address is the balancer's, so with `trusted_proxies` set the address the
+/// outermost trusted proxy recorded is used instead. Falls back to the peer
+/// address when the header has fewer entries than there are trusted proxies.
+pub fn client_ip(req: &HttpRequest, server: &ServerConfig) -> Option<String> {
+    let peer = || req.peer_addr().map(|addr| addr.ip().to_string());
+    if server.trusted_proxies == 0 {
+        return peer();
+    }
+    let name = match server.proxy_header {
+        ProxyHeader::XForwardedFor => "x-forwarded-for",
+        ProxyHeader::Forwarded => "forwarded",
+    };
+    // Repeated headers form one list, in the order they were received.
+    let values: Vec<&str> = req.headers().get_all(name).filter_map(|value| value.to_str().ok()).collect();
+    forwarded_client(&values.join(","), server.proxy_header, server.trusted_proxies)
+        .map(|ip| ip.to_string())
+        .or_else(peer)
+}
+
+/// The address `trusted_proxies` entries from the right of the header value.
+fn forwarded_client(value: &str, header: ProxyHeader, trusted_proxies: usize) -> Option<IpAddr> {
+    let entries: Vec<&str> = value.split(',').map(str::trim).filter(|entry| !entry.is_empty()).collect();
+    let entry = *entries.len().checked_sub(trusted_proxies).and_then(|index| entries.get(index))?;
+    let address = match header {
+        ProxyHeader::XForwardedFor => entry,
+        ProxyHeader::Forwarded => entry.split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+            .map(|(_, value)| value.trim().trim_matches('"'))?,
+    };
+    address.parse::<IpAddr>().ok()
+        .or_else(|| address.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| address.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// In-memory failed login counters keyed by login and by client IP. Every failure
+/// doubles the delay before the next attempt is accepted; reaching the failure
+/// limit locks the key out entirely until the lockout expires or an admin unlocks it.
+/// Password reset requests are counted the same way under keys of their own.
+/// Counters untouched for a lockout period are pruned whenever a failure is recorded.
+pub struct LoginThrottle {
+    attempts: Mutex<HashMap<String, Attempts>>,
+    max_failures_per_login: u32,
+    max_failures_per_ip: u32,
+    base_backoff: Duration,
+    lockout: Duration,
+}
+
+impl LoginThrottle {
+    pub fn new(config: &LoginThrottleConfig) -> LoginThrottle {
+        LoginThrottle {
+            attempts: Mutex::new(HashMap::new()),
+            max_failures_per_login: config.max_failures,
+            max_failures_per_ip: config.max_failures_per_ip,
+            base_backoff: Duration::from_secs(config.backoff_seconds),
+            lockout: Duration::from_secs(config.lockout_minutes * 60),
+        }
+    }
+
+    fn login_key(login: &str) -> String {
+        format!("login:{}", login)
+    }
+
+    /// Keys and failure limits for `login` and `ip`, prefixed with `scope`.
+    fn keys(&self, scope: &str, login: &str, ip: Option<&str>) -> Vec<(String, u32)> {
+        let mut keys = vec![(format!("{}login:{}", scope, login), self.max_failures_per_login)];
+        if let Some(ip) = ip {
+            keys.push((format!("{}ip:{}", scope, ip), self.max_failures_per_ip));
+        }
+        keys
+    }
+
+    /// Returns the longest block currently applying to the login or the IP.
+    pub fn check(&self, login: &str, ip: Option<&str>) -> Result<(), Blocked> {
+        self.check_keys(self.keys("", login, ip), Instant::now())
+    }
+
+    pub fn record_failure(&self, login: &str, ip: Option<&str>) {
+        self.record_keys(self.keys("", login, ip), Instant::now());
+    }
+
+    /// Like `check`, for password reset requests.
+    pub fn check_reset_request(&self, login: &str, ip: Option<&str>) -> Result<(), Blocked> {
+        self.check_keys(self.keys("reset:", login, ip), Instant::now())
+    }
+
+    /// Every reset request counts, whether or not the login exists.
+    pub fn record_reset_request(&self, login: &str, ip: Option<&str>) {
+        self.record_keys(self.keys("reset:", login, ip), Instant::now());
+    }
+
+    fn check_keys(&self, keys: Vec<(String, u32)>, now: Instant) -> Result<(), Blocked> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let mut blocked: Option<Blocked> = None;
+
+        for (key, limit) in keys {
+            let Some(entry) = attempts.get(&key) else { continue };
+            // Counters are forgotten once a lockout has passed without new failures.
+            if now.duration_since(entry.last_failure) >= self.lockout {
+                attempts.remove(&key);
+                continue;
+            }
+            if entry.blocked_until <= now {
+                continue;
+            }
+            let remaining = entry.blocked_until - now;
+            let candidate = if entry.failures >= limit {
+                Blocked::Locked(remaining)
+            } else {
+                Blocked::Backoff(remaining)
+            };
+            blocked = match (blocked, candidate) {
+                (Some(Blocked::Locked(current)), Blocked::Backoff(_)) => Some(Blocked::Locked(current)),
+                (Some(Blocked::Locked(current)), Blocked::Locked(other)) => Some(Blocked::Locked(current.max(other))),
+                (Some(Blocked::Backoff(current)), Blocked::Backoff(other)) => Some(Blocked::Backoff(current.max(other))),
+                (_, candidate) => Some(candidate),
+            };
+        }
+
+        match blocked {
+            Some(blocked) => Err(blocked),
+            None => Ok(()),
+        }
+    }
+
+    fn record_keys(&self, keys: Vec<(String, u32)>, now: Instant) {
+        let mut attempts = self.attempts.lock().unwrap();
+        attempts.retain(|_, entry| now.duration_since(entry.last_failure) < self.lockout);
+
+        for (key, limit) in keys {
+            let entry = attempts.entry(key).or_insert(Attempts { failures: 0, last_failure: now, blocked_until: now });
+            entry.failures += 1;
+            entry.last_failure = now;
+            entry.blocked_until = if entry.failures >= limit {
+                now + self.lockout
+            } else {
+                now + (self.base_backoff * 2u32.saturating_pow(entry.failures - 1)).min(self.lockout)
+            };
+        }
+    }
+
+    /// Clears the counters of the login after a successful authentication.
+    pub fn record_success(&self, login: &str) {
+        self.unlock(login);
+    }
+
+    /// Clears the counters of the login. Counters of client addresses are shared by
+    /// every login tried from them and stay as they are, so an address that is
+    /// blocked itself remains blocked. Returns whether there was anything to clear.
+    pub fn unlock(&self, login: &str) -> bool {
+        self.attempts.lock().unwrap().remove(&Self::login_key(login)).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle::new(&LoginThrottleConfig {
+            max_failures: 3,
+            max_failures_per_ip: 5,
+            backoff_seconds: 1,
+            lockout_minutes: 1,
+        })
+    }
+
+    #[test]
+    fn x_forwarded_for_skips_trusted_proxies_from_the_right() {
+        let header = ProxyHeader::XForwardedFor;
+        let client = |value: &str, proxies: usize| forwarded_client(value, header, proxies).map(|ip| ip.to_string());
+        assert_eq!(client("6.6.6.6, 1.2.3.4", 1).as_deref(), Some("1.2.3.4"));
+        assert_eq!(client("6.6.6.6, 1.2.3.4, 10.0.0.1", 2).as_deref(), Some("1.2.3.4"));
+        assert_eq!(client("1.2.3.4:5678", 1).as_deref(), Some("1.2.3.4"));
+        assert_eq!(client("[2001:db8::1]:443", 1).as_deref(), Some("2001:db8::1"));
+        assert_eq!(client("2001:db8::1", 1).as_deref(), Some("2001:db8::1"));
+        assert_eq!(client("1.2.3.4", 2), None);
+        assert_eq!(client("", 1), None);
+        assert_eq!(client("1.2.3.4, not-an-ip", 1), None);
+    }
+
+    #[test]
+    fn forwarded_uses_the_for_parameter() {
+        let client = |value: &str, proxies: usize| forwarded_client(value, ProxyHeader::Forwarded, proxies).map(|ip| ip.to_string());
+        assert_eq!(client("for=6.6.6.6, for=1.2.3.4;proto=https", 1).as_deref(), Some("1.2.3.4"));
+        assert_eq!(client("proto=https;For=\"[2001:db8::1]:443\"", 1).as_deref(), Some("2001:db8::1"));
+        assert_eq!(client("for=1.2.3.4, by=10.0.0.1", 1), None);
+    }
+
+    #[test]
+    fn failures_back_off_exponentially_then_lock() {
+        let throttle = throttle();
+        let start = Instant::now();
+        let keys = || throttle.keys("", "alice", None);
+
+        assert_eq!(throttle.check_keys(keys(), start), Ok(()));
+        throttle.record_keys(keys(), start);
+        assert_eq!(throttle.check_keys(keys(), start), Err(Blocked::Backoff(SECOND)));
+        assert_eq!(throttle.check_keys(keys(), start + SECOND), Ok(()));
+
+        throttle.record_keys(keys(), start + SECOND);
+        assert_eq!(throttle.check_keys(keys(), start + SECOND), Err(Blocked::Backoff(2 * SECOND)));
+
+        throttle.record_keys(keys(), start + 3 * SECOND);
+        assert_eq!(throttle.check_keys(keys(), start + 3 * SECOND), Err(Blocked::Locked(60 * SECOND)));
+        assert_eq!(throttle.check_keys(keys(), start + 33 * SECOND), Err(Blocked::Locked(30 * SECOND)));
+        assert_eq!(throttle.check_keys(keys(), start + 63 * SECOND), Ok(()));
+        assert!(throttle.attempts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ip_has_its_own_limit_and_survives_unlock() {
+        let throttle = throttle();
+        let start = Instant::now();
+        for (offset, login) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            throttle.record_keys(throttle.keys("", login, Some("1.2.3.4")), start + offset as u32 * SECOND);
+        }
+        let later = start + 10 * SECOND;
+        assert_eq!(throttle.check_keys(throttle.keys("", "f", Some("1.2.3.4")), later), Err(Blocked::Locked(54 * SECOND)));
+        assert_eq!(throttle.check_keys(throttle.keys("", "f", Some("5.6.7.8")), later), Ok(()));
+
+        assert!(throttle.unlock("e"));
+        assert!(!throttle.unlock("e"));
+        assert!(throttle.check_keys(throttle.keys("", "e", Some("1.2.3.4")), later).is_err());
+    }
+
+    #[test]
+    fn lock_outweighs_backoff() {
+        let throttle = throttle();
+        let start = Instant::now();
+        for _ in 0..3 {
+            throttle.record_keys(throttle.keys("", "alice", None), start);
+        }
+        throttle.record_keys(throttle.keys("", "bob", Some("1.2.3.4")), start);
+        assert_eq!(throttle.check_keys(throttle.keys("", "alice", Some("1.2.3.4")), start), Err(Blocked::Locked(60 * SECOND)));
+    }
+
+    #[test]
+    fn reset_requests_are_counted_apart_from_logins() {
+        let throttle = throttle();
+        throttle.record_reset_request("alice", None);
+        assert!(throttle.check_reset_request("alice", None).is_err());
+        assert!(throttle.check("alice", None).is_ok());
+    }
+
+    #[test]
+    fn stale_counters_are_pruned_on_the_next_failure() {
+        let throttle = throttle();
+        let start = Instant::now();
+        throttle.record_keys(throttle.keys("", "alice", Some("1.2.3.4")), start);
+        throttle.record_keys(throttle.keys("", "bob", None), start + 61 * SECOND);
+        let attempts = throttle.attempts.lock().unwrap();
+        assert_eq!(attempts.keys().collect::<Vec<_>>(), ["login:bob"]);
+    }
+}